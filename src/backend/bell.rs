@@ -0,0 +1,16 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Plays the desktop "bell" sound. Uses libcanberra's player when it is
+// installed, otherwise rings whatever terminal RoseTerm was launched from.
+pub fn ring() {
+    let played = Command::new("canberra-gtk-play")
+        .args(["--id", "bell"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    if played.is_err() {
+        let _ = std::io::stderr().write_all(b"\x07");
+    }
+}
@@ -1 +1,2 @@
+pub mod bell;
 pub mod pty;
\ No newline at end of file
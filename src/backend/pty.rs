@@ -2,18 +2,29 @@ use anyhow::Result;
 use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem, MasterPty};
 use std::{
     io::{Read, Write},
+    path::PathBuf,
     thread,
 };
 use winit::event_loop::EventLoopProxy;
 use crate::gui::window::RoseEvent;
 
+// What to run in the pty; the defaults give the user's login shell in the inherited cwd
+#[derive(Clone, Debug, Default)]
+pub struct SpawnOptions {
+    pub command: Option<Vec<String>>,
+    pub working_directory: Option<PathBuf>,
+    // Variables set on top of the inherited environment (TERM, COLORTERM, ...)
+    pub env: Vec<(String, String)>,
+}
+
 pub struct Pty {
     pub writer: Box<dyn Write + Send>,
     pub master: Box<dyn MasterPty + Send>,
 }
 
 impl Pty {
-    pub fn spawn(proxy: EventLoopProxy<RoseEvent>, cols: u16, rows: u16) -> Result<Self> {
+    // `session` tags the output and exit events so the window can tell tabs apart
+    pub fn spawn(proxy: EventLoopProxy<RoseEvent>, session: u64, cols: u16, rows: u16, options: &SpawnOptions) -> Result<Self> {
         let pty_system = NativePtySystem::default();
 
         let pair = pty_system.openpty(PtySize {
@@ -23,38 +34,47 @@ impl Pty {
             pixel_height: 0,
         })?;
 
-        let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
-
         // FIX: Make 'cmd' mutable so we can set environment variables
-        let mut cmd = CommandBuilder::new(shell);
+        let mut cmd = match &options.command {
+            Some(argv) if !argv.is_empty() => CommandBuilder::from_argv(argv.iter().map(Into::into).collect()),
+            _ => {
+                let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
+                CommandBuilder::new(shell)
+            }
+        };
 
-        // FIX: Explicitly set TERM. This fixes "TERM environment variable not set"
-        cmd.env("TERM", "xterm-256color");
+        if let Some(dir) = &options.working_directory {
+            cmd.cwd(dir);
+        }
 
-        pair.slave.spawn_command(cmd)?;
+        // TERM/COLORTERM come from the config (xterm-256color / truecolor by default),
+        // which avoids "TERM environment variable not set"
+        for (key, value) in &options.env {
+            cmd.env(key, value);
+        }
+
+        let mut child = pair.slave.spawn_command(cmd)?;
 
         let mut reader = pair.master.try_clone_reader()?;
         let writer = pair.master.take_writer()?;
         let master = pair.master;
 
         thread::spawn(move || {
-            let mut buffer = [0u8; 1024];
+            // Large reads keep a fast producer (cat of a big file) down to a
+            // handful of events per frame
+            let mut buffer = vec![0u8; 64 * 1024];
             loop {
                 match reader.read(&mut buffer) {
-                    Ok(0) => {
-                        let _ = proxy.send_event(RoseEvent::Exit);
-                        break;
-                    }
+                    Ok(0) | Err(_) => break,
                     Ok(n) => {
                         let bytes = buffer[..n].to_vec();
-                        let _ = proxy.send_event(RoseEvent::PtyOutput(bytes));
-                    }
-                    Err(_) => {
-                        let _ = proxy.send_event(RoseEvent::Exit);
-                        break;
+                        let _ = proxy.send_event(RoseEvent::PtyOutput { session, data: bytes });
                     }
                 }
             }
+            // The pty closed, so the child is gone (or going); collect its exit code
+            let status = child.wait().ok().map(|status| status.exit_code());
+            let _ = proxy.send_event(RoseEvent::Exit { session, status });
         });
 
         Ok(Self { writer, master })
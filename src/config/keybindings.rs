@@ -0,0 +1,104 @@
+use serde::Deserialize;
+
+// What a key combination does. `None` unbinds a default so the key goes to
+// the shell instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Copy,
+    // The selection with its colors as SGR sequences
+    CopyAnsi,
+    Paste,
+    SaveScrollback,
+    Search,
+    NewTab,
+    PreviousTab,
+    NextTab,
+    SplitDown,
+    SplitRight,
+    PreviousPane,
+    NextPane,
+    // Jump between OSC 133 prompt marks
+    PreviousPrompt,
+    NextPrompt,
+    ToggleFullscreen,
+    ReloadConfig,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    ScrollLineUp,
+    ScrollLineDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    None,
+}
+
+impl Action {
+    // Held down, these fire again like a typed key
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::ScrollLineUp | Action::ScrollLineDown | Action::ScrollPageUp | Action::ScrollPageDown
+        )
+    }
+}
+
+// A key with the exact set of modifiers it needs, parsed from strings like
+// "ctrl+shift+c". `key` is the lowercase key name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub key: String,
+}
+
+impl KeyBinding {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut binding = KeyBinding { ctrl: false, shift: false, alt: false, key: String::new() };
+        for part in spec.split('+').map(|part| part.trim().to_ascii_lowercase()) {
+            match part.as_str() {
+                "ctrl" | "control" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" => binding.alt = true,
+                _ if binding.key.is_empty() && !part.is_empty() => binding.key = part,
+                _ => return None,
+            }
+        }
+        (!binding.key.is_empty()).then_some(binding)
+    }
+}
+
+// Today's hotkeys; entries in the config's [keybindings] table replace or add to these
+pub const DEFAULT_KEYBINDINGS: &[(&str, Action)] = &[
+    ("ctrl+shift+c", Action::Copy),
+    ("ctrl+shift+alt+c", Action::CopyAnsi),
+    ("ctrl+shift+v", Action::Paste),
+    ("shift+insert", Action::Paste),
+    ("ctrl+shift+s", Action::SaveScrollback),
+    ("ctrl+shift+f", Action::Search),
+    ("ctrl+shift+t", Action::NewTab),
+    ("ctrl+pageup", Action::PreviousTab),
+    ("ctrl+pagedown", Action::NextTab),
+    ("ctrl+shift+d", Action::SplitDown),
+    ("ctrl+shift+e", Action::SplitRight),
+    ("ctrl+shift+tab", Action::PreviousPane),
+    ("ctrl+tab", Action::NextPane),
+    ("ctrl+shift+up", Action::PreviousPrompt),
+    ("ctrl+shift+down", Action::NextPrompt),
+    ("f11", Action::ToggleFullscreen),
+    ("ctrl+shift+r", Action::ReloadConfig),
+    ("ctrl+equals", Action::ZoomIn),
+    ("ctrl+shift+equals", Action::ZoomIn),
+    ("ctrl+plus", Action::ZoomIn),
+    ("ctrl+shift+plus", Action::ZoomIn),
+    ("ctrl+numpadadd", Action::ZoomIn),
+    ("ctrl+minus", Action::ZoomOut),
+    ("ctrl+numpadsubtract", Action::ZoomOut),
+    ("ctrl+0", Action::ZoomReset),
+    ("ctrl+numpad0", Action::ZoomReset),
+    ("shift+up", Action::ScrollLineUp),
+    ("shift+down", Action::ScrollLineDown),
+    ("shift+pageup", Action::ScrollPageUp),
+    ("shift+pagedown", Action::ScrollPageDown),
+];
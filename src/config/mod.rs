@@ -0,0 +1,3 @@
+pub mod keybindings;
+pub mod settings;
+pub mod state;
\ No newline at end of file
@@ -0,0 +1,256 @@
+use anyhow::Result;
+use log::warn;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use crate::config::keybindings::{Action, KeyBinding, DEFAULT_KEYBINDINGS};
+use crate::terminal::grid::CursorShape;
+
+// Brackets and quotes, but not / . : - so paths and URLs select whole
+pub const DEFAULT_WORD_SEPARATORS: &str = "`\"'()[]{}<>|,;";
+
+// Colors are hex strings like "#1d2021"; anything left out keeps the built-in palette
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColorsConfig {
+    pub black: Option<String>,
+    pub red: Option<String>,
+    pub green: Option<String>,
+    pub yellow: Option<String>,
+    pub blue: Option<String>,
+    pub magenta: Option<String>,
+    pub cyan: Option<String>,
+    pub white: Option<String>,
+    pub bright_black: Option<String>,
+    pub bright_red: Option<String>,
+    pub bright_green: Option<String>,
+    pub bright_yellow: Option<String>,
+    pub bright_blue: Option<String>,
+    pub bright_magenta: Option<String>,
+    pub bright_cyan: Option<String>,
+    pub bright_white: Option<String>,
+
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub cursor: Option<String>,
+    pub cursor_text: Option<String>,
+    pub selection_foreground: Option<String>,
+    pub selection_background: Option<String>,
+    pub search_foreground: Option<String>,
+    pub search_background: Option<String>,
+    pub search_current_background: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BellMode {
+    Visual,
+    Audible,
+    Both,
+    Off,
+}
+
+// What line breaks in pasted text are turned into
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteCrlf {
+    Lf,
+    Cr,
+    // Send them as they are
+    Keep,
+}
+
+impl PasteCrlf {
+    // Turns every CR LF, lone CR and lone LF into the chosen line break
+    pub fn apply(self, text: &str) -> String {
+        let newline = match self {
+            PasteCrlf::Keep => return text.to_string(),
+            PasteCrlf::Lf => "\n",
+            PasteCrlf::Cr => "\r",
+        };
+        text.replace("\r\n", "\n").replace('\r', "\n").replace('\n', newline)
+    }
+}
+
+// Who gets the mouse wheel while an application has mouse reporting on
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollMode {
+    // The application, unless Shift is held
+    Auto,
+    // Always RoseTerm's scrollback
+    Terminal,
+    // Always the application, even with Shift held
+    Application,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub colors: ColorsConfig,
+    // Let applications read the clipboard with OSC 52 queries. Off by default:
+    // any output (a file, a remote host) could otherwise ask for it.
+    pub clipboard_read: bool,
+    pub bell: BellMode,
+    // A bell in an unfocused window sets the urgency hint (taskbar flash)
+    pub urgent_on_bell: bool,
+    // Font size in pixels; Ctrl+0 returns to this after zooming
+    pub font_size: f32,
+    // Multiplier on the font's own line spacing (ascent + descent + line gap)
+    pub line_height: f32,
+    // Monospace font file; the built-in search list is used if unset
+    pub font: Option<PathBuf>,
+    // Styled faces; if unset, the -Bold / -Italic / -Oblique files next to
+    // the main font are used when present, and bold is otherwise synthesized
+    pub bold_font: Option<PathBuf>,
+    pub italic_font: Option<PathBuf>,
+    pub bold_italic_font: Option<PathBuf>,
+    // Secondary font for characters the main font lacks (emoji, symbols, CJK)
+    pub fallback_font: Option<PathBuf>,
+    // Blend glyph edges in linear light; turn off for the cheaper sRGB blend
+    pub gamma_correct: bool,
+    // 0.0 (fully transparent) to 1.0; only the default background is affected
+    pub background_opacity: f32,
+    // Space around the grid in pixels, as [horizontal, vertical]
+    pub padding: (u32, u32),
+    // Resting cursor shape and blink. Applications can change them with
+    // DECSCUSR; CSI 0 SP q and a reset come back to these.
+    pub cursor_shape: CursorShape,
+    pub cursor_blink: bool,
+    // Let SGR 5 text blink; off draws it steady
+    pub allow_blink: bool,
+    // Show SGR 8 (concealed) text instead of blanking it
+    pub reveal_concealed: bool,
+    // Backspace overstrike (X BS X, _ BS X) shows as bold and underline, as in man pages
+    pub overstrike: bool,
+    // Starting size in cells; either one overrides the default (or remembered) size
+    pub columns: Option<usize>,
+    pub rows: Option<usize>,
+    // Reopen the window at the size and position it was closed at
+    pub remember_size: bool,
+    // Keep the window (or tab) open when the shell exits and offer to restart it
+    pub hold: bool,
+    // Left Alt + key sends ESC followed by the key (readline/emacs Meta)
+    pub alt_sends_esc: bool,
+    pub paste_crlf: PasteCrlf,
+    // Ask before pasting several lines into a program without bracketed paste
+    pub paste_confirm_multiline: bool,
+    // Characters that end a word for double-click selection, on top of whitespace
+    pub word_separators: String,
+    // Wheel and trackpad scrolling move the scrollback by pixels, not lines
+    pub smooth_scroll: bool,
+    // Lines per wheel notch with Ctrl held; 0 scrolls a full page
+    pub fast_scroll_lines: usize,
+    pub scroll_mode: ScrollMode,
+    // Lines of history to keep; 0 disables scrollback, -1 keeps everything
+    pub scrollback_lines: i64,
+    // Where Ctrl+Shift+S writes scrollback dumps; defaults to the home directory
+    pub scrollback_save_dir: Option<PathBuf>,
+
+    // Environment for the child process
+    pub term: String,
+    pub colorterm: String,
+    pub env: BTreeMap<String, String>,
+
+    // "ctrl+shift+c" = "copy" and so on, on top of DEFAULT_KEYBINDINGS
+    pub keybindings: BTreeMap<String, Action>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            colors: ColorsConfig::default(),
+            clipboard_read: false,
+            bell: BellMode::Visual,
+            urgent_on_bell: true,
+            font_size: 18.0,
+            line_height: 1.0,
+            font: None,
+            bold_font: None,
+            italic_font: None,
+            bold_italic_font: None,
+            fallback_font: None,
+            gamma_correct: true,
+            background_opacity: 1.0,
+            padding: (4, 4),
+            cursor_shape: CursorShape::Block,
+            cursor_blink: true,
+            allow_blink: true,
+            reveal_concealed: false,
+            overstrike: false,
+            columns: None,
+            rows: None,
+            remember_size: true,
+            hold: false,
+            alt_sends_esc: true,
+            paste_crlf: PasteCrlf::Keep,
+            paste_confirm_multiline: true,
+            word_separators: DEFAULT_WORD_SEPARATORS.to_string(),
+            smooth_scroll: false,
+            fast_scroll_lines: 0,
+            scroll_mode: ScrollMode::Auto,
+            scrollback_lines: 10_000,
+            scrollback_save_dir: None,
+
+            term: "xterm-256color".to_string(),
+            colorterm: "truecolor".to_string(),
+            env: BTreeMap::new(),
+
+            keybindings: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/roseterm/config.toml, falling back to ~/.config
+    pub fn path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("roseterm").join("config.toml"))
+    }
+
+    // TERM and COLORTERM first, so entries in [env] can still override them
+    pub fn child_env(&self) -> Vec<(String, String)> {
+        let mut vars = vec![
+            ("TERM".to_string(), self.term.clone()),
+            ("COLORTERM".to_string(), self.colorterm.clone()),
+        ];
+        vars.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        vars
+    }
+
+    // The defaults with the configured bindings applied over them; a
+    // configured combo replaces the default for the same keys
+    pub fn keybindings(&self) -> Vec<(KeyBinding, Action)> {
+        let mut bindings: Vec<(KeyBinding, Action)> = DEFAULT_KEYBINDINGS
+            .iter()
+            .filter_map(|&(spec, action)| Some((KeyBinding::parse(spec)?, action)))
+            .collect();
+        for (spec, &action) in &self.keybindings {
+            let Some(binding) = KeyBinding::parse(spec) else {
+                warn!("Ignoring keybinding {:?}: expected something like \"ctrl+shift+c\"", spec);
+                continue;
+            };
+            bindings.retain(|(existing, _)| *existing != binding);
+            bindings.push((binding, action));
+        }
+        bindings
+    }
+
+    pub fn scrollback_limit(&self) -> Option<usize> {
+        usize::try_from(self.scrollback_lines).ok()
+    }
+
+    // A missing config file is not an error, it just means "use the defaults"
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else { return Ok(Self::default()) };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(&path)?;
+        let config = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Error parsing {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+}
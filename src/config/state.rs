@@ -0,0 +1,39 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Things RoseTerm remembers between launches, as opposed to settings the
+// user writes. Sizes and positions are in physical pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub width: u32,
+    pub height: u32,
+    // Not every platform (Wayland) reports or honours a window position
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+
+impl WindowState {
+    // $XDG_STATE_HOME/roseterm/window.toml, falling back to ~/.local/state
+    pub fn path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+        Some(base.join("roseterm").join("window.toml"))
+    }
+
+    // A missing or unreadable file just means the default geometry
+    pub fn load() -> Option<Self> {
+        let text = std::fs::read_to_string(Self::path()?).ok()?;
+        toml::from_str(&text).ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow::anyhow!("No home directory to save the window size in"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
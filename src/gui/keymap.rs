@@ -0,0 +1,102 @@
+use log::warn;
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+use crate::config::keybindings::{Action, KeyBinding};
+use crate::config::settings::Config;
+
+// The config's keybindings resolved to winit keys
+pub struct Keymap {
+    bindings: Vec<(VirtualKeyCode, KeyBinding, Action)>,
+}
+
+impl Keymap {
+    pub fn new(config: &Config) -> Self {
+        let bindings = config
+            .keybindings()
+            .into_iter()
+            .filter_map(|(binding, action)| match key_code(&binding.key) {
+                Some(key) => Some((key, binding, action)),
+                None => {
+                    warn!("Ignoring keybinding for unknown key {:?}", binding.key);
+                    None
+                }
+            })
+            .collect();
+        Self { bindings }
+    }
+
+    // The action for `key` with exactly these modifiers held
+    pub fn lookup(&self, key: VirtualKeyCode, ctrl: bool, shift: bool, alt: bool) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(k, b, _)| *k == key && b.ctrl == ctrl && b.shift == shift && b.alt == alt)
+            .map(|&(_, _, action)| action)
+    }
+
+    // A bound key pressed this frame, and its action
+    pub fn pressed(&self, input: &WinitInputHelper) -> Option<(VirtualKeyCode, Action)> {
+        let (ctrl, shift, alt) = (input.held_control(), input.held_shift(), input.held_alt());
+        self.bindings
+            .iter()
+            .filter(|(key, _, _)| input.key_pressed(*key))
+            .find_map(|&(key, _, _)| Some((key, self.lookup(key, ctrl, shift, alt)?)))
+    }
+}
+
+// Key names as written in the config: letters, digits, f1-f12 and the
+// named keys below, all lowercase
+fn key_code(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const FUNCTION_KEYS: [VirtualKeyCode; 12] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_lowercase() {
+            return Some(LETTERS[(c as u8 - b'a') as usize]);
+        }
+        if c.is_ascii_digit() {
+            return Some(DIGITS[(c as u8 - b'0') as usize]);
+        }
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return FUNCTION_KEYS.get(n.checked_sub(1)?).copied();
+    }
+    Some(match name {
+        "up" => Up,
+        "down" => Down,
+        "left" => Left,
+        "right" => Right,
+        "pageup" => PageUp,
+        "pagedown" => PageDown,
+        "home" => Home,
+        "end" => End,
+        "insert" => Insert,
+        "delete" => Delete,
+        "backspace" => Back,
+        "tab" => Tab,
+        "enter" | "return" => Return,
+        "escape" => Escape,
+        "space" => Space,
+        "plus" => Plus,
+        "minus" => Minus,
+        "equals" => Equals,
+        "comma" => Comma,
+        "period" => Period,
+        "slash" => Slash,
+        "backslash" => Backslash,
+        "semicolon" => Semicolon,
+        "apostrophe" => Apostrophe,
+        "grave" => Grave,
+        "lbracket" => LBracket,
+        "rbracket" => RBracket,
+        "numpad0" => Numpad0,
+        "numpadadd" => NumpadAdd,
+        "numpadsubtract" => NumpadSubtract,
+        _ => return None,
+    })
+}
@@ -1 +1,3 @@
+pub mod keymap;
+pub mod pane;
 pub mod window;
\ No newline at end of file
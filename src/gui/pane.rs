@@ -0,0 +1,151 @@
+use crate::backend::pty::Pty;
+use crate::renderer::font::Rect;
+use crate::terminal::grid::Terminal;
+
+// One shell: its screen state and the pty it talks to
+pub struct Session {
+    pub id: u64,
+    pub terminal: Terminal,
+    pub pty: Pty,
+    // The child exited and we're holding the pane open (config `hold`)
+    pub exited: bool,
+    // Smooth scrolling: the view sits this far (0..1 of a line) further
+    // back than the terminal's scroll_offset, while that is still the first value
+    pub smooth_scroll: Option<(usize, f32)>,
+}
+
+impl Session {
+    // The part-line offset to draw with; any other change to the scroll
+    // position snaps back to whole lines
+    pub fn scroll_fraction(&self) -> f32 {
+        match self.smooth_scroll {
+            Some((offset, fraction)) if offset == self.terminal.scroll_offset => fraction,
+            _ => 0.0,
+        }
+    }
+}
+
+// Where a split puts the new pane relative to the old one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Right,
+    Down,
+}
+
+// How a tab's area is divided: a binary tree whose leaves are session ids
+#[derive(Clone, Debug)]
+pub enum Layout {
+    Leaf(u64),
+    Split { direction: Direction, first: Box<Layout>, second: Box<Layout> },
+}
+
+impl Layout {
+    // Replaces the `target` leaf with a split holding it and `new`
+    pub fn split(&mut self, target: u64, new: u64, direction: Direction) -> bool {
+        match self {
+            Layout::Leaf(id) if *id == target => {
+                *self = Layout::Split {
+                    direction,
+                    first: Box::new(Layout::Leaf(target)),
+                    second: Box::new(Layout::Leaf(new)),
+                };
+                true
+            }
+            Layout::Leaf(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(target, new, direction) || second.split(target, new, direction)
+            }
+        }
+    }
+
+    // Drops the `target` leaf; its sibling takes over the space
+    pub fn remove(&mut self, target: u64) -> bool {
+        let Layout::Split { first, second, .. } = self else { return false };
+        if matches!(**first, Layout::Leaf(id) if id == target) {
+            *self = (**second).clone();
+            return true;
+        }
+        if matches!(**second, Layout::Leaf(id) if id == target) {
+            *self = (**first).clone();
+            return true;
+        }
+        first.remove(target) || second.remove(target)
+    }
+
+    // The rectangle of every pane, in reading order, and the 1px dividers
+    // between them
+    pub fn rects(&self, area: Rect, panes: &mut Vec<(u64, Rect)>, dividers: &mut Vec<Rect>) {
+        match self {
+            Layout::Leaf(id) => panes.push((*id, area)),
+            Layout::Split { direction, first, second } => {
+                let (a, divider, b) = match direction {
+                    Direction::Right => {
+                        let w = area.width.saturating_sub(1) / 2;
+                        (
+                            Rect { width: w, ..area },
+                            Rect { x: area.x + w, width: 1.min(area.width), ..area },
+                            Rect { x: area.x + w + 1, width: area.width.saturating_sub(w + 1), ..area },
+                        )
+                    }
+                    Direction::Down => {
+                        let h = area.height.saturating_sub(1) / 2;
+                        (
+                            Rect { height: h, ..area },
+                            Rect { y: area.y + h, height: 1.min(area.height), ..area },
+                            Rect { y: area.y + h + 1, height: area.height.saturating_sub(h + 1), ..area },
+                        )
+                    }
+                };
+                first.rects(a, panes, dividers);
+                dividers.push(divider);
+                second.rects(b, panes, dividers);
+            }
+        }
+    }
+}
+
+// A tab: its shells, how they're laid out, and which one has focus
+pub struct Tab {
+    pub sessions: Vec<Session>,
+    pub layout: Layout,
+    pub focus: u64,
+}
+
+impl Tab {
+    pub fn new(session: Session) -> Self {
+        let id = session.id;
+        Self { sessions: vec![session], layout: Layout::Leaf(id), focus: id }
+    }
+
+    pub fn focused(&self) -> &Session {
+        self.sessions.iter().find(|session| session.id == self.focus).expect("focused pane exists")
+    }
+
+    pub fn focused_mut(&mut self) -> &mut Session {
+        let focus = self.focus;
+        self.sessions.iter_mut().find(|session| session.id == focus).expect("focused pane exists")
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Session> {
+        self.sessions.iter_mut().find(|session| session.id == id)
+    }
+
+    // Splits the focused pane and moves focus to the new one
+    pub fn split(&mut self, session: Session, direction: Direction) {
+        let id = session.id;
+        self.layout.split(self.focus, id, direction);
+        self.sessions.push(session);
+        self.focus = id;
+    }
+
+    // Closes a pane; focus moves to the first remaining one if it had it
+    pub fn remove(&mut self, id: u64) {
+        self.layout.remove(id);
+        self.sessions.retain(|session| session.id != id);
+        if self.focus == id {
+            if let Some(session) = self.sessions.first() {
+                self.focus = session.id;
+            }
+        }
+    }
+}
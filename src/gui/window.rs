@@ -1,31 +1,130 @@
 use anyhow::Result;
-use log::error;
-use pixels::{Pixels, SurfaceTexture};
+use log::{error, info, warn};
+use pixels::{wgpu, Pixels, PixelsBuilder, SurfaceTexture};
 use winit::{
-    dpi::LogicalSize,
-    event::{Event, VirtualKeyCode},
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    event::{Event, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy},
+    monitor::MonitorHandle,
+    window::{Fullscreen, UserAttentionType, WindowBuilder},
 };
 use winit_input_helper::{WinitInputHelper, TextChar};
-use vte::Parser;
 use arboard::Clipboard;
-use std::time::{Duration, Instant};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::io::Write; // Explicitly import Write for the pty writer
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 
-use crate::backend::pty::Pty;
-use crate::terminal::grid::Terminal;
-use crate::renderer::font::FontRenderer;
+use crate::backend::bell;
+use crate::backend::pty::{Pty, SpawnOptions};
+use crate::config::keybindings::Action;
+use crate::config::settings::{BellMode, Config, ScrollMode};
+use crate::config::state::WindowState;
+use crate::gui::keymap::Keymap;
+use crate::gui::pane::{Direction, Session, Tab};
+use crate::terminal::grid::{HostCommand, MouseEncoding, MouseMode, Terminal};
+use crate::renderer::font::{FontRenderer, Rect};
 
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+// Two clicks on the same cell within this select a word
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+// How often the view scrolls while a selection is dragged past the edge
+const AUTOSCROLL_INTERVAL: Duration = Duration::from_millis(50);
+// Output-driven redraws are capped to roughly one per 60Hz frame
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+// The longest a synchronized update (mode 2026) may hold back drawing, in
+// case the program never ends it
+const SYNC_TIMEOUT: Duration = Duration::from_millis(150);
+
+// Pty events carry the id of the session (pane) they came from
 #[derive(Debug)]
 pub enum RoseEvent {
-    PtyOutput(Vec<u8>),
-    Exit,
+    PtyOutput { session: u64, data: Vec<u8> },
+    Exit { session: u64, status: Option<u32> },
+    // SIGHUP: re-read the config file
+    ReloadConfig,
+}
+
+// winit_input_helper's button indices (left, right, middle) and the
+// button numbers mouse reports use for them
+const MOUSE_BUTTONS: [(usize, u8); 3] = [(0, 0), (1, 2), (2, 1)];
+
+// SGR reports are unbounded and name the released button. The legacy form
+// packs each value into a byte offset by 32, so positions past column or row
+// 223 are clamped, and every release is reported as button 3.
+fn encode_mouse(encoding: MouseEncoding, button: u8, x: usize, y: usize, release: bool) -> Vec<u8> {
+    match encoding {
+        MouseEncoding::Sgr => {
+            let suffix = if release { 'm' } else { 'M' };
+            format!("\x1b[<{};{};{}{}", button, x + 1, y + 1, suffix).into_bytes()
+        }
+        MouseEncoding::Normal => {
+            let button = if release { 3 } else { button };
+            let coord = |v: usize| (v + 1 + 32).min(255) as u8;
+            vec![0x1b, b'[', b'M', button + 32, coord(x), coord(y)]
+        }
+    }
+}
+
+// xterm modifier parameter: 1 + Shift(1) + Alt(2) + Ctrl(4)
+fn modifier_param(shift: bool, alt: bool, ctrl: bool) -> u8 {
+    1 + shift as u8 + 2 * alt as u8 + 4 * ctrl as u8
+}
+
+// Arrow keys switch to the SS3 form when the app enabled DECCKM; modified
+// keys always use ESC [ 1 ; mod X
+fn arrow_key(app_cursor_keys: bool, modifier: u8, letter: char) -> String {
+    if modifier > 1 {
+        format!("\x1b[1;{}{}", modifier, letter)
+    } else if app_cursor_keys {
+        format!("\x1bO{}", letter)
+    } else {
+        format!("\x1b[{}", letter)
+    }
+}
+
+// Keys like PageUp (ESC [ 5 ~), with the modifier as a second parameter
+fn tilde_key(code: u8, modifier: u8) -> String {
+    if modifier > 1 {
+        format!("\x1b[{};{}~", code, modifier)
+    } else {
+        format!("\x1b[{}~", code)
+    }
+}
+
+// X11/Wayland PRIMARY selection: set on select, pasted with middle-click
+#[cfg(target_os = "linux")]
+fn set_primary(clipboard: &mut Clipboard, text: String) {
+    use arboard::{LinuxClipboardKind, SetExtLinux};
+    let _ = clipboard.set().clipboard(LinuxClipboardKind::Primary).text(text);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_primary(_clipboard: &mut Clipboard, _text: String) {}
+
+#[cfg(target_os = "linux")]
+fn get_primary(clipboard: &mut Clipboard) -> Option<String> {
+    use arboard::{GetExtLinux, LinuxClipboardKind};
+    clipboard.get().clipboard(LinuxClipboardKind::Primary).text().ok()
 }
 
-fn encode_mouse(button: u8, x: usize, y: usize, release: bool) -> String {
-    let suffix = if release { 'm' } else { 'M' };
-    format!("\x1b[<{};{};{}{}", button, x + 1, y + 1, suffix)
+#[cfg(not(target_os = "linux"))]
+fn get_primary(_clipboard: &mut Clipboard) -> Option<String> {
+    None
+}
+
+// Hand a hyperlink target to the desktop's default handler
+fn open_uri(uri: &str) {
+    #[cfg(target_os = "macos")]
+    let opener = std::process::Command::new("open").arg(uri).spawn();
+    #[cfg(target_os = "windows")]
+    let opener = std::process::Command::new("cmd").args(["/C", "start", "", uri]).spawn();
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let opener = std::process::Command::new("xdg-open").arg(uri).spawn();
+
+    if let Err(e) = opener {
+        error!("Failed to open {}: {}", uri, e);
+    }
 }
 
 // Helper to map A-Z to Control Codes (1-26)
@@ -68,136 +167,756 @@ fn ctrl_key_to_byte(key: VirtualKeyCode) -> Option<u8> {
 }
 
 pub struct RoseWindow {
+    config: Config,
     window: winit::window::Window,
     pixels: Pixels,
-    pty: Pty,
-    terminal: Terminal,
-    parser: Parser,
+    // Kept so new tabs can be spawned and shells respawned after they exit
+    proxy: EventLoopProxy<RoseEvent>,
+    spawn: SpawnOptions,
+    // Input goes to the focused pane of the active tab
+    tabs: Vec<Tab>,
+    active: usize,
+    next_session_id: u64,
     renderer: FontRenderer,
+    keymap: Keymap,
     clipboard: Clipboard,
     is_selecting: bool,
+    // Pointer position during a selection drag, and when to auto-scroll next
+    drag_pos: (f32, f32),
+    autoscroll_deadline: Instant,
+    // Last cell the pointer was seen in while the app gets mouse reports, to skip sub-cell motion
+    last_mouse_cell: Option<(usize, usize)>,
+    // Time and cell of the last left click, to spot double-clicks
+    last_click: Option<(Instant, (usize, usize))>,
+
+    // Visual bell: the frame is inverted until this deadline
+    flash_until: Option<Instant>,
+    last_bell: Option<Instant>,
+
+    // Blink phase shared by the cursor and SGR 5 text, and when it next flips
+    blink_on: bool,
+    blink_deadline: Instant,
+    // Whether the window has keyboard focus; without it the cursor is hollow
+    window_focused: bool,
 
     // Key Repeat State
     last_key: Option<VirtualKeyCode>,
     repeat_deadline: Instant,
+
+    // New pty output since the last frame, drawn at most once per FRAME_INTERVAL
+    output_dirty: bool,
+    last_frame: Instant,
+    // When a pane in the active tab started a synchronized update
+    sync_since: Option<Instant>,
+    // A key was pressed since the last frame, so its echo is drawn right away
+    input_since_frame: bool,
+
+    // Scrollback search: the query, and whether it is still being typed
+    search_query: Option<String>,
+    search_editing: bool,
+
+    // A multi-line paste waiting for the user to confirm it
+    pending_paste: Option<String>,
+
+    // Window size before going fullscreen, restored when leaving it
+    windowed_size: Option<PhysicalSize<u32>>,
 }
 
 impl RoseWindow {
-    pub fn new(event_loop: &EventLoop<RoseEvent>) -> Result<Self> {
-        let size = LogicalSize::new(800.0, 600.0);
-        let window = WindowBuilder::new()
+    pub fn new(event_loop: &EventLoop<RoseEvent>, config: &Config, spawn: &SpawnOptions) -> Result<Self> {
+        // The font is needed to size the window in cells, so load it with
+        // the scale of the monitor the window will most likely open on
+        let monitor = event_loop.primary_monitor().or_else(|| event_loop.available_monitors().next());
+        let scale_factor = monitor.as_ref().map_or(1.0, |monitor| monitor.scale_factor());
+        let mut renderer = FontRenderer::new(config, scale_factor as f32)?;
+
+        let mut builder = WindowBuilder::new()
             .with_title("RoseTerm")
-            .with_inner_size(size)
-            .build(event_loop)?;
+            .with_inner_size(LogicalSize::new(800.0, 600.0))
+            .with_transparent(config.background_opacity < 1.0);
+        if let Some(state) = config.remember_size.then(|| restored_geometry(event_loop)).flatten() {
+            builder = builder.with_inner_size(PhysicalSize::new(state.width, state.height));
+            if let (Some(x), Some(y)) = (state.x, state.y) {
+                builder = builder.with_position(PhysicalPosition::new(x, y));
+            }
+        }
+        if let Some(size) = configured_size(config, &renderer, monitor.as_ref()) {
+            builder = builder.with_inner_size(size);
+        }
+        let window = builder.build(event_loop)?;
+        if window.scale_factor() != scale_factor {
+            renderer.set_scale_factor(window.scale_factor() as f32);
+        }
 
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        let pixels = Pixels::new(window_size.width, window_size.height, surface_texture)?;
-
-        let renderer = FontRenderer::new()?;
-
-        let cols = (window_size.width as f32 / renderer.char_width) as usize;
-        let rows = (window_size.height as f32 / renderer.char_height) as usize;
+        let mut builder = PixelsBuilder::new(window_size.width, window_size.height, surface_texture);
+        if config.background_opacity < 1.0 {
+            // Whether the compositor honours this depends on the platform
+            builder = builder.clear_color(wgpu::Color::TRANSPARENT);
+        }
+        let pixels = builder.build()?;
 
-        let terminal = Terminal::new(cols, rows);
-        let proxy = event_loop.create_proxy();
-        let pty = Pty::spawn(proxy, cols as u16, rows as u16)?;
-        let parser = Parser::new();
         let clipboard = Clipboard::new()?;
 
-        Ok(Self {
+        let mut app = Self {
+            config: config.clone(),
             window,
             pixels,
-            pty,
-            terminal,
-            parser,
+            proxy: event_loop.create_proxy(),
+            spawn: spawn.clone(),
+            tabs: Vec::new(),
+            active: 0,
+            next_session_id: 0,
             renderer,
+            keymap: Keymap::new(config),
             clipboard,
             is_selecting: false,
+            drag_pos: (0.0, 0.0),
+            autoscroll_deadline: Instant::now(),
+            last_mouse_cell: None,
+            last_click: None,
+
+            flash_until: None,
+            last_bell: None,
+
+            blink_on: true,
+            window_focused: true,
+            blink_deadline: Instant::now() + CURSOR_BLINK_INTERVAL,
 
             last_key: None,
             repeat_deadline: Instant::now(),
-        })
+
+            output_dirty: false,
+            last_frame: Instant::now(),
+            sync_since: None,
+            input_since_frame: false,
+
+            search_query: None,
+            search_editing: false,
+
+            pending_paste: None,
+
+            windowed_size: None,
+        };
+        let size = app.window.inner_size();
+        let session = app.new_session(app.renderer.content_rect(size.width, size.height))?;
+        app.tabs.push(Tab::new(session));
+        Ok(app)
+    }
+
+    fn session(&self) -> &Session {
+        self.tabs[self.active].focused()
+    }
+
+    fn session_mut(&mut self) -> &mut Session {
+        self.tabs[self.active].focused_mut()
+    }
+
+    // Looks a session up in any tab
+    fn session_by_id(&mut self, id: u64) -> Option<&mut Session> {
+        self.tabs.iter_mut().find_map(|tab| tab.get_mut(id))
+    }
+
+    // Where each pane of a tab sits in the window, plus the dividers
+    fn pane_rects(&self, tab: usize) -> (Vec<(u64, Rect)>, Vec<Rect>) {
+        let size = self.window.inner_size();
+        let (mut panes, mut dividers) = (Vec::new(), Vec::new());
+        let area = self.renderer.content_rect(size.width, size.height);
+        self.tabs[tab].layout.rects(area, &mut panes, &mut dividers);
+        (panes, dividers)
+    }
+
+    fn focused_rect(&self) -> Rect {
+        let focus = self.tabs[self.active].focus;
+        let (panes, _) = self.pane_rects(self.active);
+        panes.into_iter().find(|&(id, _)| id == focus).map(|(_, rect)| rect).unwrap_or_default()
+    }
+
+    // A shell sized to fit a pane at `area`
+    fn new_session(&mut self, area: Rect) -> Result<Session> {
+        let (cols, rows) = self.renderer.grid_size(area.width, area.height);
+        let (cols, rows) = (cols.max(1), rows.max(1));
+
+        let mut terminal = Terminal::new(cols, rows);
+        terminal.base_palette = self.renderer.theme.palette();
+        terminal.pixel_size = self.renderer.text_area(area.width, area.height);
+        terminal.set_default_cursor(self.config.cursor_shape, self.config.cursor_blink);
+        terminal.overstrike = self.config.overstrike;
+        terminal.set_scrollback_lines(self.config.scrollback_limit());
+
+        let id = self.next_session_id;
+        self.next_session_id += 1;
+        let pty = Pty::spawn(self.proxy.clone(), id, cols as u16, rows as u16, &self.spawn)?;
+        Ok(Session { id, terminal, pty, exited: false, smooth_scroll: None })
+    }
+
+    // Ctrl+Shift+T: open a tab after the current one and switch to it
+    fn open_tab(&mut self) {
+        let had_tab_bar = self.renderer.tab_bar;
+        self.renderer.tab_bar = true;
+        let size = self.window.inner_size();
+        match self.new_session(self.renderer.content_rect(size.width, size.height)) {
+            Ok(session) => {
+                self.tabs.insert(self.active + 1, Tab::new(session));
+                self.switch_tab(self.active + 1);
+                if !had_tab_bar { self.relayout_current(); }
+            }
+            Err(e) => {
+                self.renderer.tab_bar = had_tab_bar;
+                error!("Failed to open a tab: {}", e);
+            }
+        }
+    }
+
+    // Drops a tab whose last pane closed; false if it was the last tab
+    fn close_tab(&mut self, index: usize) -> bool {
+        if self.tabs.len() == 1 { return false; }
+        self.tabs.remove(index);
+        if self.active > index || self.active == self.tabs.len() {
+            self.active -= 1;
+        }
+        self.switch_tab(self.active);
+        if self.tabs.len() == 1 {
+            self.renderer.tab_bar = false;
+            self.relayout_current();
+        }
+        true
+    }
+
+    fn switch_tab(&mut self, index: usize) {
+        self.active = index;
+        self.on_focus_moved();
+    }
+
+    // Ctrl+Shift+D / Ctrl+Shift+E: split the focused pane in two
+    fn split_pane(&mut self, direction: Direction) {
+        let area = self.focused_rect();
+        let half = match direction {
+            Direction::Right => Rect { width: area.width.saturating_sub(1) / 2, ..area },
+            Direction::Down => Rect { height: area.height.saturating_sub(1) / 2, ..area },
+        };
+        match self.new_session(half) {
+            Ok(session) => {
+                self.tabs[self.active].split(session, direction);
+                self.on_focus_moved();
+                self.relayout_current();
+            }
+            Err(e) => error!("Failed to split the pane: {}", e),
+        }
+    }
+
+    // Ctrl+Tab / Ctrl+Shift+Tab: focus the next or previous pane in reading
+    // order. False with a single pane, so the key reaches the program.
+    fn cycle_pane(&mut self, forward: bool) -> bool {
+        let (panes, _) = self.pane_rects(self.active);
+        let count = panes.len();
+        if count <= 1 { return false; }
+        let Some(current) = panes.iter().position(|&(id, _)| id == self.tabs[self.active].focus) else { return false };
+        let next = if forward { (current + 1) % count } else { (current + count - 1) % count };
+        self.focus_pane(panes[next].0);
+        true
+    }
+
+    // The pane of the active tab under a window position
+    fn pane_at(&self, x: f32, y: f32) -> Option<u64> {
+        let (panes, _) = self.pane_rects(self.active);
+        panes.into_iter().find(|(_, rect)| {
+            x >= rect.x as f32 && x < (rect.x + rect.width) as f32
+                && y >= rect.y as f32 && y < (rect.y + rect.height) as f32
+        }).map(|(id, _)| id)
+    }
+
+    fn focus_pane(&mut self, id: u64) {
+        if self.tabs[self.active].focus == id { return; }
+        self.tabs[self.active].focus = id;
+        self.on_focus_moved();
+    }
+
+    // F11. The size change comes back as a resize event, which re-fits the
+    // grid and ptys like any other resize.
+    fn toggle_fullscreen(&mut self) {
+        if self.window.fullscreen().is_some() {
+            self.window.set_fullscreen(None);
+            if let Some(size) = self.windowed_size.take() {
+                self.window.set_inner_size(size);
+            }
+        } else {
+            self.windowed_size = Some(self.window.inner_size());
+            self.window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+    }
+
+    // Remembers the window's size and position for the next launch. In
+    // fullscreen that's the size it will return to.
+    fn save_geometry(&self) {
+        if !self.config.remember_size { return; }
+        let (size, position) = match self.window.fullscreen() {
+            Some(_) => (self.windowed_size.unwrap_or_else(|| self.window.inner_size()), None),
+            None => (self.window.inner_size(), self.window.outer_position().ok()),
+        };
+        let state = WindowState {
+            width: size.width,
+            height: size.height,
+            x: position.map(|p| p.x),
+            y: position.map(|p| p.y),
+        };
+        if let Err(e) = state.save() {
+            error!("Failed to save the window size: {}", e);
+        }
+    }
+
+    // Search and drag state belong to the pane they started in
+    fn on_focus_moved(&mut self) {
+        if self.search_query.is_some() { self.close_search(); }
+        self.is_selecting = false;
+        self.window.set_title(&self.session().terminal.title);
+        self.window.request_redraw();
+    }
+
+    // A pane's shell exited: close the pane, then the tab once it's empty.
+    // Returns false when that was the last tab.
+    fn close_session(&mut self, id: u64) -> bool {
+        let Some(index) = self.tabs.iter().position(|tab| tab.sessions.iter().any(|s| s.id == id)) else { return true };
+        if self.tabs[index].sessions.len() == 1 {
+            return self.close_tab(index);
+        }
+        self.tabs[index].remove(id);
+        if index == self.active { self.on_focus_moved(); }
+        self.relayout_current();
+        true
     }
 
     pub fn draw(&mut self) {
+        // Mid-frame: draw once the update ends or times out instead
+        if self.sync_since.is_some_and(|since| Instant::now() < since + SYNC_TIMEOUT) {
+            self.output_dirty = true;
+            return;
+        }
+        self.last_frame = Instant::now();
+        self.input_since_frame = false;
         let width = self.window.inner_size().width;
+        let text_on = self.blink_on || !self.config.allow_blink;
+        let (panes, dividers) = self.pane_rects(self.active);
+        self.renderer.blink_visible = false;
         let frame = self.pixels.frame_mut();
-        self.renderer.draw(&self.terminal, frame, width);
+        let tab = &self.tabs[self.active];
+        for (id, area) in panes {
+            let Some(session) = tab.sessions.iter().find(|session| session.id == id) else { continue };
+            let focused = id == tab.focus;
+            // An unfocused window's hollow cursor doesn't blink
+            let cursor_on = self.blink_on || !session.terminal.cursor_blink || !self.window_focused;
+            self.renderer.draw(&session.terminal, frame, width, area, cursor_on && focused, self.window_focused, text_on, session.scroll_fraction());
+            if let (Some(query), true, true) = (&self.search_query, self.search_editing, focused) {
+                let prompt = format!("Search: {}", query);
+                self.renderer.draw_status_line(frame, width, session.terminal.rows - 1, &prompt);
+            }
+            if let (Some(text), true) = (&self.pending_paste, focused) {
+                let lines = paste_line_count(text);
+                let prompt = format!("Paste {} line{}? (y/n)", lines, if lines == 1 { "" } else { "s" });
+                self.renderer.draw_status_line(frame, width, session.terminal.rows - 1, &prompt);
+            }
+        }
+        for divider in dividers {
+            self.renderer.draw_divider(frame, width, divider);
+        }
+        if self.renderer.tab_bar {
+            let titles: Vec<&str> = self.tabs.iter().map(|tab| tab.focused().terminal.title.as_str()).collect();
+            self.renderer.draw_tab_bar(frame, width, &titles, self.active);
+        }
+        if self.flash_until.is_some_and(|until| Instant::now() < until) {
+            for pixel in frame.chunks_exact_mut(4) {
+                pixel[0] = 255 - pixel[0];
+                pixel[1] = 255 - pixel[1];
+                pixel[2] = 255 - pixel[2];
+            }
+        }
         if let Err(e) = self.pixels.render() {
             error!("pixels.render() failed: {}", e);
         }
     }
 
     // Helper to send special keys (Arrows, Home, End, etc)
-    fn process_special_key(&mut self, key: VirtualKeyCode, held_shift: bool, held_ctrl: bool) -> bool {
+    fn process_special_key(&mut self, key: VirtualKeyCode, held_shift: bool, held_ctrl: bool, held_alt: bool) -> bool {
+        let app_cursor = self.session().terminal.app_cursor_keys;
+        let modifier = modifier_param(held_shift, held_alt, held_ctrl);
         match key {
             VirtualKeyCode::Return => {
-                if self.terminal.scroll_offset > 0 { self.terminal.scroll_offset = 0; }
-                // FIX: Send \r (Carriage Return) instead of \n
-                let _ = self.pty.writer.write_all(b"\r");
+                self.session_mut().terminal.scroll_to_bottom();
+                // FIX: Send \r (Carriage Return) instead of \n, or CR LF under LNM
+                let enter: &[u8] = if self.session().terminal.newline_mode { b"\r\n" } else { b"\r" };
+                self.pty_write(enter);
                 true
             }
             VirtualKeyCode::Escape => {
-                let _ = self.pty.writer.write_all(b"\x1b");
+                self.pty_write(b"\x1b");
+                true
+            }
+            VirtualKeyCode::Tab => {
+                // Shift+Tab is back-tab (CBT)
+                if held_shift { self.pty_write(b"\x1b[Z"); }
+                else { self.pty_write(b"\t"); }
                 true
             }
             VirtualKeyCode::Back => {
-                if self.terminal.scroll_offset > 0 { self.terminal.scroll_offset = 0; }
-                let _ = self.pty.writer.write_all(b"\x7f");
+                self.session_mut().terminal.scroll_to_bottom();
+                self.pty_write(b"\x7f");
                 true
             }
             VirtualKeyCode::Delete => {
-                let _ = self.pty.writer.write_all(b"\x1b[3~");
+                self.pty_write(b"\x1b[3~");
                 true
             }
 
             // ARROWS
-            VirtualKeyCode::Up => {
-                if held_shift && !held_ctrl { self.terminal.scroll_up(1); }
-                else { let _ = self.pty.writer.write_all(b"\x1b[A"); }
-                true
+            VirtualKeyCode::Up => { self.pty_write(arrow_key(app_cursor, modifier, 'A').as_bytes()); true }
+            VirtualKeyCode::Down => { self.pty_write(arrow_key(app_cursor, modifier, 'B').as_bytes()); true }
+            VirtualKeyCode::Right => { self.pty_write(arrow_key(app_cursor, modifier, 'C').as_bytes()); true }
+            VirtualKeyCode::Left => { self.pty_write(arrow_key(app_cursor, modifier, 'D').as_bytes()); true }
+
+            // NAVIGATION
+            VirtualKeyCode::PageUp => { self.pty_write(tilde_key(5, modifier).as_bytes()); true }
+            VirtualKeyCode::PageDown => { self.pty_write(tilde_key(6, modifier).as_bytes()); true }
+            VirtualKeyCode::Home => { self.pty_write(arrow_key(false, modifier, 'H').as_bytes()); true }
+            VirtualKeyCode::End => { self.pty_write(arrow_key(false, modifier, 'F').as_bytes()); true }
+
+            // FUNCTION KEYS (xterm: SS3 for F1-F4, CSI ~ for the rest)
+            VirtualKeyCode::F1 => { self.pty_write(b"\x1bOP"); true }
+            VirtualKeyCode::F2 => { self.pty_write(b"\x1bOQ"); true }
+            VirtualKeyCode::F3 => { self.pty_write(b"\x1bOR"); true }
+            VirtualKeyCode::F4 => { self.pty_write(b"\x1bOS"); true }
+            VirtualKeyCode::F5 => { self.pty_write(b"\x1b[15~"); true }
+            VirtualKeyCode::F6 => { self.pty_write(b"\x1b[17~"); true }
+            VirtualKeyCode::F7 => { self.pty_write(b"\x1b[18~"); true }
+            VirtualKeyCode::F8 => { self.pty_write(b"\x1b[19~"); true }
+            VirtualKeyCode::F9 => { self.pty_write(b"\x1b[20~"); true }
+            VirtualKeyCode::F10 => { self.pty_write(b"\x1b[21~"); true }
+            VirtualKeyCode::F11 => { self.pty_write(b"\x1b[23~"); true }
+            VirtualKeyCode::F12 => { self.pty_write(b"\x1b[24~"); true }
+
+            _ => false
+        }
+    }
+
+    // Re-derive every pane's grid size from its share of the window and the
+    // cell metrics, then resize its terminal and pty to match
+    fn relayout(&mut self, width: u32, height: u32) {
+        let area = self.renderer.content_rect(width, height);
+        for tab in &mut self.tabs {
+            let (mut panes, mut dividers) = (Vec::new(), Vec::new());
+            tab.layout.rects(area, &mut panes, &mut dividers);
+            for (id, rect) in panes {
+                let (cols, rows) = self.renderer.grid_size(rect.width, rect.height);
+                let Some(session) = tab.get_mut(id) else { continue };
+                if cols > 0 && rows > 0 {
+                    session.terminal.resize(cols, rows);
+                    session.terminal.pixel_size = self.renderer.text_area(rect.width, rect.height);
+                    let _ = session.pty.resize(rows as u16, cols as u16);
+                }
             }
-            VirtualKeyCode::Down => {
-                if held_shift && !held_ctrl { self.terminal.scroll_down(1); }
-                else { let _ = self.pty.writer.write_all(b"\x1b[B"); }
-                true
+        }
+        self.window.request_redraw();
+    }
+
+    // The tab bar or a split changed how the window is shared out
+    fn relayout_current(&mut self) {
+        let size = self.window.inner_size();
+        self.relayout(size.width, size.height);
+    }
+
+    // Moving between monitors: re-rasterize at the new scale and re-fit the grid
+    fn on_scale_factor_changed(&mut self, scale_factor: f64) {
+        self.renderer.set_scale_factor(scale_factor as f32);
+        let size = self.window.inner_size();
+        let _ = self.pixels.resize_surface(size.width, size.height);
+        let _ = self.pixels.resize_buffer(size.width, size.height);
+        self.relayout(size.width, size.height);
+    }
+
+    // Moves the view `lines` back (or forward, if negative) through the
+    // scrollback, keeping the part of a line left over for the next frame
+    fn smooth_scroll(&mut self, lines: f32) {
+        let session = self.session_mut();
+        let before = session.terminal.scroll_offset as f32 + session.scroll_fraction();
+        let position = (before + lines).clamp(0.0, session.terminal.history.len() as f32);
+        session.terminal.set_scroll_offset(position as usize);
+        session.smooth_scroll = Some((session.terminal.scroll_offset, position.fract()));
+        if position != before {
+            self.window.request_redraw();
+        }
+    }
+
+    // Lines per Ctrl+wheel notch; 0 in the config means a screenful
+    fn fast_scroll_lines(&self) -> usize {
+        match self.config.fast_scroll_lines {
+            0 => self.session().terminal.rows,
+            lines => lines,
+        }
+    }
+
+    fn set_font_size(&mut self, size: f32) {
+        self.renderer.set_font_size(size);
+        self.relayout_current();
+    }
+
+    // Ctrl+Shift+R or SIGHUP: re-read config.toml and apply it to the fonts,
+    // colors and every open terminal. A config that doesn't parse (or a font
+    // that doesn't load) leaves the current settings alone. The window's
+    // transparency is fixed at startup, and new environment variables only
+    // reach shells started afterwards.
+    fn reload_config(&mut self) {
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Keeping the current settings: {}", e);
+                return;
             }
-            VirtualKeyCode::Right => { let _ = self.pty.writer.write_all(b"\x1b[C"); true }
-            VirtualKeyCode::Left => { let _ = self.pty.writer.write_all(b"\x1b[D"); true }
+        };
+        let mut renderer = match FontRenderer::new(&config, self.window.scale_factor() as f32) {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                error!("Keeping the current settings: {}", e);
+                return;
+            }
+        };
+        // Keep a zoomed font size unless the configured size changed
+        if config.font_size == self.config.font_size {
+            renderer.set_font_size(self.renderer.font_size);
+        }
+        renderer.tab_bar = self.renderer.tab_bar;
+        self.renderer = renderer;
 
-            // NAVIGATION
-            VirtualKeyCode::PageUp => {
-                if held_shift { self.terminal.scroll_up(10); }
-                else { let _ = self.pty.writer.write_all(b"\x1b[5~"); }
-                true
+        for session in self.tabs.iter_mut().flat_map(|tab| tab.sessions.iter_mut()) {
+            session.terminal.base_palette = self.renderer.theme.palette();
+            session.terminal.set_default_cursor(config.cursor_shape, config.cursor_blink);
+            session.terminal.overstrike = config.overstrike;
+            session.terminal.set_scrollback_lines(config.scrollback_limit());
+        }
+        self.spawn.env = config.child_env();
+        self.keymap = Keymap::new(&config);
+        self.config = config;
+        self.reset_blink();
+        self.relayout_current();
+        info!("Reloaded the config");
+    }
+
+    // Without `hold` the pane closes, and the window with the last one.
+    // Returns false when the window should close.
+    pub fn on_child_exit(&mut self, id: u64, status: Option<u32>) -> bool {
+        if !self.config.hold {
+            return self.close_session(id);
+        }
+        let Some(session) = self.session_by_id(id) else { return true };
+        // Already reported, by a failed write or the exit event
+        if session.exited { return true; }
+        session.exited = true;
+        let message = match status {
+            Some(code) => format!("\x1b[0m\r\n[Process exited with code {} \u{2014} press Enter to restart]", code),
+            None => "\x1b[0m\r\n[Process exited \u{2014} press Enter to restart]".to_string(),
+        };
+        self.on_pty_data(id, message.as_bytes());
+        self.window.request_redraw();
+        true
+    }
+
+    // Sends input to the focused pane's shell
+    fn pty_write(&mut self, bytes: &[u8]) {
+        let id = self.session().id;
+        self.write_to(id, bytes);
+    }
+
+    // A failed write means the shell's end of the pty is gone; treat it like
+    // the exit event so the pane says so instead of eating keystrokes
+    fn write_to(&mut self, id: u64, bytes: &[u8]) {
+        let Some(session) = self.session_by_id(id) else { return };
+        if session.exited { return; }
+        if let Err(e) = session.pty.writer.write_all(bytes) {
+            warn!("Writing to the shell failed: {}", e);
+            let _ = self.proxy.send_event(RoseEvent::Exit { session: id, status: None });
+        }
+    }
+
+    fn respawn(&mut self) {
+        let id = self.session().id;
+        let cols = self.session().terminal.cols as u16;
+        let rows = self.session().terminal.rows as u16;
+        match Pty::spawn(self.proxy.clone(), id, cols, rows, &self.spawn) {
+            Ok(pty) => {
+                self.session_mut().pty = pty;
+                self.session_mut().exited = false;
+                self.on_pty_data(id, b"\r\n");
             }
-            VirtualKeyCode::PageDown => {
-                if held_shift { self.terminal.scroll_down(10); }
-                else { let _ = self.pty.writer.write_all(b"\x1b[6~"); }
-                true
+            Err(e) => error!("Failed to restart the shell: {}", e),
+        }
+    }
+
+    // Runs a bound action. False lets the key through to the shell, for
+    // actions that don't apply right now.
+    fn perform(&mut self, action: Action) -> bool {
+        match action {
+            Action::Copy | Action::CopyAnsi => {
+                let terminal = &self.session().terminal;
+                let text = if action == Action::CopyAnsi {
+                    terminal.get_selected_ansi_text()
+                } else {
+                    terminal.get_selected_text()
+                };
+                if !text.is_empty() { let _ = self.clipboard.set_text(text); }
+            }
+            Action::Paste => {
+                if let Ok(text) = self.clipboard.get_text() {
+                    self.paste(&text);
+                }
+            }
+            Action::SaveScrollback => self.save_scrollback(),
+            Action::Search => {
+                self.search_query = Some(String::new());
+                self.search_editing = true;
+                self.window.request_redraw();
+            }
+            Action::NewTab => self.open_tab(),
+            Action::PreviousTab | Action::NextTab => {
+                let count = self.tabs.len();
+                if count < 2 { return false; }
+                let step = if action == Action::NextTab { 1 } else { count - 1 };
+                self.switch_tab((self.active + step) % count);
+            }
+            Action::SplitDown => self.split_pane(Direction::Down),
+            Action::SplitRight => self.split_pane(Direction::Right),
+            Action::PreviousPane | Action::NextPane => return self.cycle_pane(action == Action::NextPane),
+            Action::PreviousPrompt | Action::NextPrompt => {
+                if self.session().terminal.is_alt_screen() { return false; }
+                self.session_mut().terminal.jump_to_prompt(action == Action::PreviousPrompt);
+                self.window.request_redraw();
+            }
+            Action::ToggleFullscreen => self.toggle_fullscreen(),
+            Action::ReloadConfig => self.reload_config(),
+            Action::ZoomIn => self.set_font_size(self.renderer.font_size + 1.0),
+            Action::ZoomOut => self.set_font_size(self.renderer.font_size - 1.0),
+            Action::ZoomReset => self.set_font_size(self.config.font_size),
+            Action::ScrollLineUp | Action::ScrollLineDown | Action::ScrollPageUp | Action::ScrollPageDown => {
+                // Full-screen apps on the alternate screen want these keys themselves
+                if self.session().terminal.is_alt_screen() { return false; }
+                let terminal = &mut self.session_mut().terminal;
+                let moved = match action {
+                    Action::ScrollLineUp => terminal.scroll_up(1),
+                    Action::ScrollLineDown => terminal.scroll_down(1),
+                    Action::ScrollPageUp => terminal.scroll_up(10),
+                    _ => terminal.scroll_down(10),
+                };
+                if moved { self.window.request_redraw(); }
             }
-            VirtualKeyCode::Home => { let _ = self.pty.writer.write_all(b"\x1b[H"); true }
-            VirtualKeyCode::End => { let _ = self.pty.writer.write_all(b"\x1b[F"); true }
+            Action::None => return false,
+        }
+        true
+    }
 
-            _ => false
+    // Sends clipboard text to the shell, with its line breaks converted and
+    // wrapped in bracketed paste markers if the application asked for them.
+    // Without them, control characters are dropped and several lines need
+    // confirming first, since each line break would run a command.
+    fn paste(&mut self, text: &str) {
+        let text = self.config.paste_crlf.apply(text);
+        if self.session().terminal.bracketed_paste {
+            // An end marker inside the text would let it escape the bracket
+            let text = text.replace("\x1b[201~", "");
+            self.send_paste(&format!("\x1b[200~{}\x1b[201~", text));
+            return;
+        }
+        let text: String = text.chars().filter(|&c| !c.is_control() || matches!(c, '\t' | '\n' | '\r')).collect();
+        // Even one line ending in a break runs as soon as it arrives
+        if self.config.paste_confirm_multiline && text.contains(['\n', '\r']) {
+            self.pending_paste = Some(text);
+            self.window.request_redraw();
+        } else {
+            self.send_paste(&text);
+        }
+    }
+
+    fn send_paste(&mut self, text: &str) {
+        self.session_mut().terminal.scroll_to_bottom();
+        self.pty_write(text.as_bytes());
+    }
+
+    // While a paste waits for confirmation the next key answers it: y or
+    // Enter sends it, anything else typed (or Escape) drops it
+    fn handle_paste_prompt(&mut self, input: &WinitInputHelper) {
+        let confirm = input.key_pressed(VirtualKeyCode::Y) || input.key_pressed(VirtualKeyCode::Return);
+        let cancel = !input.text().is_empty() || input.key_pressed(VirtualKeyCode::Escape);
+        if !confirm && !cancel { return; }
+        if let (Some(text), true) = (self.pending_paste.take(), confirm) {
+            self.send_paste(&text);
         }
+        self.window.request_redraw();
+    }
+
+    // A held key fires again: its action if it repeats, or its escape sequence
+    fn repeat_key(&mut self, key: VirtualKeyCode, input: &WinitInputHelper) {
+        let (ctrl, shift, alt) = (input.held_control(), input.held_shift(), input.held_alt());
+        if let Some(action) = self.keymap.lookup(key, ctrl, shift, alt).filter(|action| action.repeats()) {
+            if self.perform(action) { return; }
+        }
+        self.process_special_key(key, shift, ctrl, alt);
     }
 
     pub fn handle_input(&mut self, input: &WinitInputHelper) {
+        if self.pending_paste.is_some() {
+            self.handle_paste_prompt(input);
+            return;
+        }
+
+        // Hotkeys, from the keybinding table
+        if let Some((key, action)) = self.keymap.pressed(input) {
+            if self.perform(action) {
+                if action.repeats() {
+                    self.last_key = Some(key);
+                    self.repeat_deadline = Instant::now() + Duration::from_millis(500);
+                }
+                return;
+            }
+        }
+
+        if self.search_query.is_some() && self.handle_search_input(input) {
+            self.window.request_redraw();
+            return;
+        }
+
+        // Nothing is listening on the pty; Enter starts a new shell
+        if self.session().exited {
+            if input.key_pressed(VirtualKeyCode::Return) {
+                self.respawn();
+            }
+            return;
+        }
+
         let is_copy_paste_hotkey = input.held_control() && input.held_shift();
 
-        // 1. Handle Regular Text (No Control held)
-        if !input.held_control() && !input.held_alt() {
-            if !input.text().is_empty() {
-               for text_char in input.text() {
-                   if let TextChar::Char(c) = text_char {
-                       let mut bytes = [0; 4];
-                       let s = c.encode_utf8(&mut bytes);
-                       if self.terminal.scroll_offset > 0 { self.terminal.scroll_offset = 0; }
-                       let _ = self.pty.writer.write_all(s.as_bytes());
-                   }
-               }
+        // 1. Handle Regular Text (No Control held). Right Alt (AltGr) composes
+        // characters, so only the left Alt suppresses plain text.
+        if !input.held_control() && !input.key_held(VirtualKeyCode::LAlt) && !input.text().is_empty() {
+            for text_char in input.text() {
+                if let TextChar::Char(c) = text_char {
+                    // Tab, Escape and friends are sent by process_special_key
+                    if c.is_control() { continue; }
+                    let mut bytes = [0; 4];
+                    let s = c.encode_utf8(&mut bytes);
+                    self.session_mut().terminal.scroll_to_bottom();
+                    self.pty_write(s.as_bytes());
+                }
+            }
+        }
+
+        // 1b. Alt as Meta: Alt+b arrives as ESC b. Only the left Alt counts,
+        // since the right one is AltGr on international layouts.
+        if self.config.alt_sends_esc && input.key_held(VirtualKeyCode::LAlt) && !input.held_control() {
+            for text_char in input.text() {
+                if let TextChar::Char(c) = text_char {
+                    let mut bytes = [0; 4];
+                    let s = c.encode_utf8(&mut bytes);
+                    self.session_mut().terminal.scroll_to_bottom();
+                    self.pty_write(b"\x1b");
+                    self.pty_write(s.as_bytes());
+                }
             }
         }
 
@@ -215,8 +934,8 @@ impl RoseWindow {
             for key in keys {
                 if input.key_pressed(key) {
                     if let Some(byte) = ctrl_key_to_byte(key) {
-                        if self.terminal.scroll_offset > 0 { self.terminal.scroll_offset = 0; }
-                        let _ = self.pty.writer.write_all(&[byte]);
+                        self.session_mut().terminal.scroll_to_bottom();
+                        self.pty_write(&[byte]);
                     }
                 }
             }
@@ -226,13 +945,17 @@ impl RoseWindow {
         let mut handled_special = false;
         let keys_to_check = [
             VirtualKeyCode::Return, VirtualKeyCode::Escape, VirtualKeyCode::Back, VirtualKeyCode::Delete,
+            VirtualKeyCode::Tab,
             VirtualKeyCode::Up, VirtualKeyCode::Down, VirtualKeyCode::Left, VirtualKeyCode::Right,
-            VirtualKeyCode::PageUp, VirtualKeyCode::PageDown, VirtualKeyCode::Home, VirtualKeyCode::End
+            VirtualKeyCode::PageUp, VirtualKeyCode::PageDown, VirtualKeyCode::Home, VirtualKeyCode::End,
+            VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4,
+            VirtualKeyCode::F5, VirtualKeyCode::F6, VirtualKeyCode::F7, VirtualKeyCode::F8,
+            VirtualKeyCode::F9, VirtualKeyCode::F10, VirtualKeyCode::F11, VirtualKeyCode::F12
         ];
 
         for &key in &keys_to_check {
             if input.key_pressed(key) {
-                self.process_special_key(key, input.held_shift(), input.held_control());
+                self.process_special_key(key, input.held_shift(), input.held_control(), input.held_alt());
                 self.last_key = Some(key);
                 self.repeat_deadline = Instant::now() + Duration::from_millis(500);
                 handled_special = true;
@@ -244,7 +967,7 @@ impl RoseWindow {
             if let Some(key) = self.last_key {
                 if input.key_held(key) {
                     if Instant::now() >= self.repeat_deadline {
-                        self.process_special_key(key, input.held_shift(), input.held_control());
+                        self.repeat_key(key, input);
                         self.repeat_deadline = Instant::now() + Duration::from_millis(50);
                     }
                 } else {
@@ -253,103 +976,492 @@ impl RoseWindow {
             }
         }
 
-        // --- COPY / PASTE ---
-        if input.held_shift() && input.key_pressed(VirtualKeyCode::Insert) {
-             if let Ok(text) = self.clipboard.get_text() {
-                 if self.terminal.scroll_offset > 0 { self.terminal.scroll_offset = 0; }
-                 let _ = self.pty.writer.write_all(text.as_bytes());
-             }
-        }
-
-        if input.held_control() && input.held_shift() {
-            if input.key_pressed(VirtualKeyCode::C) {
-                let text = self.terminal.get_selected_text();
-                if !text.is_empty() { let _ = self.clipboard.set_text(text); }
-            }
-            if input.key_pressed(VirtualKeyCode::V) {
-                if let Ok(text) = self.clipboard.get_text() {
-                    let _ = self.pty.writer.write_all(text.as_bytes());
+        // --- MOUSE HANDLING ---
+        if let Some((mx, my)) = input.mouse() {
+            // Clicking into another pane focuses it first
+            if (0..3).any(|button| input.mouse_pressed(button)) {
+                if let Some(id) = self.pane_at(mx, my) {
+                    self.focus_pane(id);
                 }
             }
-        }
+            let (col, row) = self.renderer.pixel_to_cell(self.focused_rect(), mx, my);
 
-        // --- MOUSE HANDLING ---
-        if let Some((mx, my)) = input.mouse() {
-            let col = (mx / self.renderer.char_width) as usize;
-            let row = (my / self.renderer.char_height) as usize;
+            let hovered = self.session().terminal.link_at(col, row);
+            if hovered != self.session().terminal.hovered_link {
+                self.session_mut().terminal.hovered_link = hovered;
+                self.window.request_redraw();
+            }
 
             let force_selection = input.held_shift();
-            let app_mouse_mode = self.terminal.mouse_reporting && !force_selection;
+            let mouse_mode = self.session().terminal.mouse_mode;
+            let encoding = self.session().terminal.mouse_encoding;
+            let app_mouse_mode = mouse_mode != MouseMode::Off && !force_selection;
 
             if app_mouse_mode {
-                if input.mouse_pressed(0) {
-                    let _ = self.pty.writer.write_all(encode_mouse(0, col, row, false).as_bytes());
-                }
-                if input.mouse_released(0) {
-                    let _ = self.pty.writer.write_all(encode_mouse(0, col, row, true).as_bytes());
-                }
-                if input.mouse_pressed(1) {
-                    let _ = self.pty.writer.write_all(encode_mouse(2, col, row, false).as_bytes());
+                let mut clicked = false;
+                for (index, button) in MOUSE_BUTTONS {
+                    if input.mouse_pressed(index) {
+                        self.pty_write(&encode_mouse(encoding, button, col, row, false));
+                        clicked = true;
+                    }
+                    if input.mouse_released(index) {
+                        self.pty_write(&encode_mouse(encoding, button, col, row, true));
+                        clicked = true;
+                    }
                 }
-                let scroll = input.scroll_diff();
-                if scroll > 0.0 {
-                    let _ = self.pty.writer.write_all(encode_mouse(64, col, row, false).as_bytes());
-                } else if scroll < 0.0 {
-                      let _ = self.pty.writer.write_all(encode_mouse(65, col, row, false).as_bytes());
+
+                // Motion is reported once per cell: 1002 only while a button
+                // is held, 1003 always (as button 3, "none")
+                let moved = self.last_mouse_cell != Some((col, row));
+                self.last_mouse_cell = Some((col, row));
+                let held = MOUSE_BUTTONS.iter().find(|&&(index, _)| input.mouse_held(index)).map(|&(_, button)| button);
+                let motion = match (mouse_mode, held) {
+                    (MouseMode::ButtonEvent | MouseMode::AnyEvent, Some(button)) => Some(button),
+                    (MouseMode::AnyEvent, None) => Some(3),
+                    _ => None,
+                };
+                if let Some(button) = motion.filter(|_| moved && !clicked) {
+                    // + 32 marks a motion event
+                    self.pty_write(&encode_mouse(encoding, button + 32, col, row, false));
                 }
             } else {
-                if input.mouse_pressed(0) {
+                let double_click = input.mouse_pressed(0) && self.last_click.is_some_and(|(at, cell)| {
+                    cell == (col, row) && at.elapsed() < DOUBLE_CLICK_INTERVAL
+                });
+                if double_click {
+                    self.last_click = None;
+                    self.is_selecting = false;
+                    let separators = self.config.word_separators.clone();
+                    self.session_mut().terminal.select_word(col, row, &separators);
+                    let text = self.session().terminal.get_selected_text();
+                    if !text.is_empty() { set_primary(&mut self.clipboard, text); }
+                    self.window.request_redraw();
+                } else if input.mouse_pressed(0) {
+                    self.last_click = Some((Instant::now(), (col, row)));
                     self.is_selecting = true;
-                    self.terminal.start_selection(col, row);
+                    // Alt+drag selects a rectangle
+                    self.session_mut().terminal.start_selection(col, row, input.held_alt());
                     self.window.request_redraw();
                 }
 
                 if self.is_selecting {
-                    self.terminal.update_selection(col, row);
-                    self.window.request_redraw();
+                    let end = self.session().terminal.selection_end;
+                    self.session_mut().terminal.update_selection(col, row);
+                    self.drag_pos = (mx, my);
+                    if self.session().terminal.selection_end != end { self.window.request_redraw(); }
                 }
 
-                if input.mouse_released(0) {
+                // A double-click's release leaves its word selected
+                if input.mouse_released(0) && self.is_selecting {
                     self.is_selecting = false;
-                    if self.terminal.selection_start == self.terminal.selection_end {
-                        self.terminal.clear_selection();
+                    if self.session().terminal.selection_start == self.session().terminal.selection_end {
+                        // A plain click (no drag) on a hyperlink opens it
+                        if let Some(link) = self.session().terminal.link_at(col, row) {
+                            open_uri(&self.session().terminal.links[link as usize]);
+                        }
+                        self.session_mut().terminal.clear_selection();
                         self.window.request_redraw();
+                    } else {
+                        let text = self.session().terminal.get_selected_text();
+                        if !text.is_empty() { set_primary(&mut self.clipboard, text); }
                     }
                 }
 
                 if input.mouse_released(1) {
-                    let text = self.terminal.get_selected_text();
+                    let text = self.session().terminal.get_selected_text();
                     if !text.is_empty() {
                         let _ = self.clipboard.set_text(text);
-                        self.terminal.clear_selection();
+                        self.session_mut().terminal.clear_selection();
+                        self.window.request_redraw();
+                    }
+                }
+
+                if input.mouse_released(2) {
+                    if let Some(text) = get_primary(&mut self.clipboard) {
+                        self.paste(&text);
+                    }
+                }
+            }
+
+            let scroll = input.scroll_diff();
+            let wheel_to_app = match self.config.scroll_mode {
+                ScrollMode::Auto => app_mouse_mode,
+                ScrollMode::Terminal => false,
+                ScrollMode::Application => mouse_mode != MouseMode::Off,
+            };
+            if scroll != 0.0 {
+                if wheel_to_app {
+                    let button = if scroll > 0.0 { 64 } else { 65 };
+                    self.pty_write(&encode_mouse(encoding, button, col, row, false));
+                } else if self.session().terminal.is_alt_screen()
+                    && mouse_mode == MouseMode::Off
+                    && self.config.scroll_mode != ScrollMode::Terminal
+                {
+                    // The alternate screen has no scrollback, so let pagers like
+                    // less/man scroll themselves by sending them arrow keys
+                    let letter = if scroll > 0.0 { 'A' } else { 'B' };
+                    let seq = arrow_key(self.session().terminal.app_cursor_keys, 1, letter).repeat(3);
+                    self.pty_write(seq.as_bytes());
+                } else {
+                    // Ctrl+wheel scrolls fast. The wheel isn't part of the
+                    // keybinding table, so no binding can claim it for zoom.
+                    let lines = if input.held_control() { self.fast_scroll_lines() } else { 3 };
+                    // At either end the wheel does nothing, not even redraw
+                    if self.config.smooth_scroll {
+                        self.smooth_scroll(scroll * lines as f32);
+                    } else if scroll > 0.0 {
+                        if self.session_mut().terminal.scroll_up(lines) { self.window.request_redraw(); }
+                    } else if self.session_mut().terminal.scroll_down(lines) {
                         self.window.request_redraw();
                     }
                 }
+            }
+        }
+    }
+
+    pub fn on_focus_changed(&mut self, focused: bool) {
+        self.window_focused = focused;
+        if focused {
+            self.window.request_user_attention(None);
+        }
+        self.window.request_redraw();
+        if self.session().terminal.focus_reporting {
+            let report: &[u8] = if focused { b"\x1b[I" } else { b"\x1b[O" };
+            self.pty_write(report);
+        }
+    }
+
+    // Keep the cursor solid while the user is typing
+    fn reset_blink(&mut self) {
+        if !self.blink_on { self.window.request_redraw(); }
+        self.blink_on = true;
+        self.blink_deadline = Instant::now() + CURSOR_BLINK_INTERVAL;
+    }
+
+    // The blink timer only runs while something on screen blinks: the
+    // focused cursor (which DECSCUSR can switch) or, if allowed, SGR 5 text
+    fn blinking(&self) -> bool {
+        let terminal = &self.session().terminal;
+        let cursor = self.window_focused && terminal.cursor_blink && terminal.cursor_visible && terminal.scroll_offset == 0;
+        cursor || (self.config.allow_blink && self.renderer.blink_visible)
+    }
+
+    // Expire the bell flash and advance the blink phase
+    fn tick_timers(&mut self) {
+        let now = Instant::now();
+        if self.flash_until.is_some_and(|until| now >= until) {
+            self.flash_until = None;
+            self.window.request_redraw();
+        }
+        if self.blinking() && now >= self.blink_deadline {
+            self.blink_on = !self.blink_on;
+            self.blink_deadline = now + CURSOR_BLINK_INTERVAL;
+            self.window.request_redraw();
+        }
+        if self.autoscroll_direction().is_some() && now >= self.autoscroll_deadline {
+            self.autoscroll();
+            self.autoscroll_deadline = now + AUTOSCROLL_INTERVAL;
+        }
+        if self.frame_deadline().is_some_and(|due| now >= due) {
+            self.output_dirty = false;
+            self.window.request_redraw();
+        }
+    }
+
+    // While dragging a selection on or past the top row: Some(true), scroll
+    // back. On or past the bottom row: Some(false), scroll forward.
+    fn autoscroll_direction(&self) -> Option<bool> {
+        let terminal = &self.session().terminal;
+        if !self.is_selecting || terminal.is_alt_screen() { return None; }
+        // pixel_to_cell() maps anything above the grid to row 0
+        let (_, row) = self.renderer.pixel_to_cell(self.focused_rect(), self.drag_pos.0, self.drag_pos.1);
+        if row == 0 && terminal.scroll_offset < terminal.history.len() {
+            Some(true)
+        } else if row >= terminal.rows - 1 && terminal.scroll_offset > 0 {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn autoscroll(&mut self) {
+        let (col, _) = self.renderer.pixel_to_cell(self.focused_rect(), self.drag_pos.0, self.drag_pos.1);
+        match self.autoscroll_direction() {
+            Some(true) => {
+                self.session_mut().terminal.scroll_up(1);
+                self.session_mut().terminal.update_selection(col, 0);
+            }
+            Some(false) => {
+                self.session_mut().terminal.scroll_down(1);
+                let bottom = self.session().terminal.rows - 1;
+                self.session_mut().terminal.update_selection(col, bottom);
+            }
+            None => return,
+        }
+        self.window.request_redraw();
+    }
+
+    // When pending output should be drawn, if there is any
+    fn frame_deadline(&self) -> Option<Instant> {
+        if !self.output_dirty {
+            return None;
+        }
+        let due = if self.input_since_frame { self.last_frame } else { self.last_frame + FRAME_INTERVAL };
+        Some(self.sync_since.map_or(due, |since| due.max(since + SYNC_TIMEOUT)))
+    }
+
+    // The earliest time a timer needs the event loop to wake up
+    fn next_deadline(&self) -> Option<Instant> {
+        let blink = self.blinking().then_some(self.blink_deadline);
+        let autoscroll = self.autoscroll_direction().map(|_| self.autoscroll_deadline);
+        [self.flash_until, blink, self.frame_deadline(), autoscroll].into_iter().flatten().min()
+    }
+
+    // Keys while a search is open. Returns true when the input was consumed;
+    // anything else closes the search and is handled as normal input.
+    fn handle_search_input(&mut self, input: &WinitInputHelper) -> bool {
+        if input.key_pressed(VirtualKeyCode::Escape) {
+            self.close_search();
+            return true;
+        }
+
+        if self.search_editing {
+            let Some(query) = self.search_query.as_mut() else { return false };
+            for text_char in input.text() {
+                if let TextChar::Char(c) = text_char {
+                    if !c.is_control() { query.push(c); }
+                }
+            }
+            if input.key_pressed(VirtualKeyCode::Back) {
+                query.pop();
+            }
+            if input.key_pressed(VirtualKeyCode::Return) {
+                let query = query.clone();
+                self.search_editing = false;
+                self.session_mut().terminal.search_matches = self.session().terminal.search(&query);
+                // Start from the most recent match, closest to the prompt
+                self.session_mut().terminal.search_current = self.session().terminal.search_matches.len().checked_sub(1);
+                self.jump_to_search_match();
+            }
+            return true;
+        }
+
+        // n goes back through older output, N forward
+        let count = self.session().terminal.search_matches.len();
+        if input.key_pressed(VirtualKeyCode::N) && count > 0 {
+            let current = self.session().terminal.search_current.unwrap_or(0);
+            self.session_mut().terminal.search_current = Some(if input.held_shift() {
+                (current + 1) % count
+            } else {
+                (current + count - 1) % count
+            });
+            self.jump_to_search_match();
+            return true;
+        }
+
+        if !input.text().is_empty() || input.key_pressed(VirtualKeyCode::Return) {
+            self.close_search();
+        }
+        false
+    }
+
+    fn jump_to_search_match(&mut self) {
+        if let Some((line, _)) = self.session().terminal.search_current.and_then(|i| self.session().terminal.search_matches.get(i)) {
+            let line = *line;
+            self.session_mut().terminal.center_line(line);
+        }
+    }
+
+    fn close_search(&mut self) {
+        self.search_query = None;
+        self.search_editing = false;
+        self.session_mut().terminal.clear_search();
+    }
+
+    // Ctrl+Shift+S: dump scrollback and screen to roseterm-<unix time>.txt
+    fn save_scrollback(&self) {
+        let dir = self.config.scrollback_save_dir.clone()
+            .or_else(|| std::env::var_os("HOME").map(PathBuf::from))
+            .unwrap_or_else(std::env::temp_dir);
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let path = dir.join(format!("roseterm-{}.txt", stamp));
+        match std::fs::write(&path, self.session().terminal.scrollback_to_string()) {
+            Ok(()) => info!("Saved scrollback to {}", path.display()),
+            Err(e) => error!("Could not save scrollback to {}: {}", path.display(), e),
+        }
+    }
+
+    fn ring_bell(&mut self) {
+        // Debounce so a flood of BELs doesn't strobe the screen
+        let now = Instant::now();
+        if self.last_bell.is_some_and(|last| now < last + Duration::from_millis(200)) {
+            return;
+        }
+        self.last_bell = Some(now);
+
+        let mode = self.config.bell;
+        if mode == BellMode::Visual || mode == BellMode::Both {
+            self.flash_until = Some(now + Duration::from_millis(100));
+        }
+        if mode == BellMode::Audible || mode == BellMode::Both {
+            bell::ring();
+        }
+        if self.config.urgent_on_bell && !self.window_focused {
+            self.window.request_user_attention(Some(UserAttentionType::Critical));
+        }
+    }
 
-                let scroll = input.scroll_diff();
-                if scroll > 0.0 { self.terminal.scroll_up(3); self.window.request_redraw(); }
-                else if scroll < 0.0 { self.terminal.scroll_down(3); self.window.request_redraw(); }
+    fn handle_host_command(&mut self, id: u64, command: HostCommand) {
+        match command {
+            HostCommand::SetClipboard { primary, text } => {
+                if primary {
+                    set_primary(&mut self.clipboard, text);
+                } else {
+                    let _ = self.clipboard.set_text(text);
+                }
+            }
+            HostCommand::QueryClipboard { selection, bell_terminated } => {
+                if !self.config.clipboard_read { return; }
+                let text = self.clipboard.get_text().unwrap_or_default();
+                let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+                let reply = format!("\x1b]52;{};{}{}", selection, BASE64.encode(text), terminator);
+                if let Some(session) = self.session_by_id(id) {
+                    session.terminal.respond(&reply);
+                }
+            }
+            HostCommand::ResizeWindow { rows, cols } => {
+                // The resulting resize event re-dimensions the grid and pty
+                let (width, height) = self.renderer.window_size(cols, rows);
+                self.window.set_inner_size(PhysicalSize::new(width, height));
             }
         }
     }
 
-    pub fn on_pty_data(&mut self, data: Vec<u8>) {
-        for byte in data {
-            self.parser.advance(&mut self.terminal, byte);
+    // Output for the session `id`. Only panes in the active tab are redrawn
+    // and only the focused one sets the window title, but every session
+    // still gets its replies.
+    pub fn on_pty_data(&mut self, id: u64, data: &[u8]) {
+        let Some(session) = self.session_by_id(id) else { return };
+        session.terminal.feed(data);
+        let bell = std::mem::take(&mut session.terminal.bell_pending);
+        let commands = std::mem::take(&mut session.terminal.pending_commands);
+
+        if bell { self.ring_bell(); }
+        for command in commands {
+            self.handle_host_command(id, command);
+        }
+        if let Some(session) = self.session_by_id(id) {
+            if !session.terminal.pending_output.is_empty() {
+                let replies = std::mem::take(&mut session.terminal.pending_output);
+                self.write_to(id, &replies);
+            }
+        }
+
+        let tab = &self.tabs[self.active];
+        if tab.focus == id {
+            self.window.set_title(&self.session().terminal.title);
+        }
+        if tab.sessions.iter().any(|session| session.id == id) {
+            if tab.sessions.iter().any(|session| session.terminal.sync_update) {
+                self.sync_since.get_or_insert_with(Instant::now);
+            } else {
+                self.sync_since = None;
+            }
+            self.output_dirty = true;
+        } else if self.renderer.tab_bar {
+            // Keep the background tab's title current in the tab bar, at
+            // the same frame rate as output in the active tab
+            self.output_dirty = true;
         }
-        self.window.set_title(&self.terminal.title);
     }
 }
 
-pub fn run() -> Result<()> {
-    let event_loop = EventLoop::with_user_event();
-    let mut app = RoseWindow::new(&event_loop)?;
+// The lines a paste runs as: CR LF, CR and LF each end one, and a final
+// break doesn't start another
+fn paste_line_count(text: &str) -> usize {
+    let text = text.replace("\r\n", "\n");
+    let text = text.strip_suffix(['\n', '\r']).unwrap_or(&text);
+    text.split(['\n', '\r']).count()
+}
+
+// The window size for the configured columns and rows, if either is set.
+// The other keeps the default size, and both are shrunk to fit the monitor.
+fn configured_size(config: &Config, renderer: &FontRenderer, monitor: Option<&MonitorHandle>) -> Option<PhysicalSize<u32>> {
+    if config.columns.is_none() && config.rows.is_none() { return None; }
+    let scale_factor = monitor.map_or(1.0, |monitor| monitor.scale_factor());
+    let default: PhysicalSize<u32> = LogicalSize::new(800.0, 600.0).to_physical(scale_factor);
+    let (width, height) = renderer.window_size(config.columns.unwrap_or(1).max(1), config.rows.unwrap_or(1).max(1));
+    let mut size = PhysicalSize::new(
+        if config.columns.is_some() { width } else { default.width },
+        if config.rows.is_some() { height } else { default.height },
+    );
+    if let Some(monitor) = monitor {
+        size.width = size.width.min(monitor.size().width);
+        size.height = size.height.min(monitor.size().height);
+    }
+    Some(size)
+}
+
+// The saved window geometry, fitted onto a monitor that still exists: the
+// one it was on (or the primary one), shrunk to fit and moved fully onto it
+fn restored_geometry(event_loop: &EventLoop<RoseEvent>) -> Option<WindowState> {
+    let mut state = WindowState::load()?;
+    let on_monitor = |monitor: &MonitorHandle, x: i32, y: i32| {
+        let (pos, size) = (monitor.position(), monitor.size());
+        (pos.x..pos.x + size.width as i32).contains(&x) && (pos.y..pos.y + size.height as i32).contains(&y)
+    };
+    let monitor = state.x.zip(state.y)
+        .and_then(|(x, y)| event_loop.available_monitors().find(|m| on_monitor(m, x, y)))
+        .or_else(|| event_loop.primary_monitor())
+        .or_else(|| event_loop.available_monitors().next());
+
+    if let Some(monitor) = monitor {
+        let (pos, size) = (monitor.position(), monitor.size());
+        state.width = state.width.min(size.width);
+        state.height = state.height.min(size.height);
+        state.x = state.x.map(|x| x.clamp(pos.x, pos.x + (size.width - state.width) as i32));
+        state.y = state.y.map(|y| y.clamp(pos.y, pos.y + (size.height - state.height) as i32));
+    }
+    (state.width > 0 && state.height > 0).then_some(state)
+}
+
+// `kill -HUP` reloads the config, like Ctrl+Shift+R
+#[cfg(unix)]
+fn reload_on_sighup(proxy: EventLoopProxy<RoseEvent>) {
+    use signal_hook::{consts::SIGHUP, iterator::Signals};
+    match Signals::new([SIGHUP]) {
+        Ok(mut signals) => {
+            std::thread::spawn(move || {
+                for _ in signals.forever() {
+                    let _ = proxy.send_event(RoseEvent::ReloadConfig);
+                }
+            });
+        }
+        Err(e) => error!("Can't listen for SIGHUP: {}", e),
+    }
+}
+
+pub fn run(mut spawn: SpawnOptions) -> Result<()> {
+    let config = Config::load().unwrap_or_else(|e| {
+        error!("{}", e);
+        Config::default()
+    });
+    spawn.env = config.child_env();
+    let event_loop = EventLoopBuilder::<RoseEvent>::with_user_event().build();
+    let mut app = RoseWindow::new(&event_loop, &config, &spawn)?;
+    #[cfg(unix)]
+    reload_on_sighup(event_loop.create_proxy());
     let mut input = WinitInputHelper::new();
 
     event_loop.run(move |event, _, control_flow| {
-        // Smart wait logic
+        app.tick_timers();
+
+        // Smart wait logic. Pending output counts as a deadline, so a burst
+        // of PtyOutput events is drawn once when the next frame is due.
         if app.last_key.is_some() {
              *control_flow = ControlFlow::Poll;
+        } else if let Some(deadline) = app.next_deadline() {
+             *control_flow = ControlFlow::WaitUntil(deadline);
         } else {
              *control_flow = ControlFlow::Wait;
         }
@@ -359,39 +1471,48 @@ pub fn run() -> Result<()> {
         }
 
         match event {
-            Event::UserEvent(RoseEvent::Exit) => {
-                *control_flow = ControlFlow::Exit;
-                return;
+            Event::UserEvent(RoseEvent::Exit { session, status }) => {
+                let keep_open = app.on_child_exit(session, status);
+                if !keep_open {
+                    app.save_geometry();
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
+            Event::UserEvent(RoseEvent::PtyOutput { session, ref data }) => {
+                app.on_pty_data(session, data);
+            }
+            Event::UserEvent(RoseEvent::ReloadConfig) => {
+                app.reload_config();
             }
-            Event::UserEvent(RoseEvent::PtyOutput(ref data)) => {
-                 app.on_pty_data(data.clone());
-                 app.window.request_redraw();
+            Event::WindowEvent { event: WindowEvent::Focused(focused), .. } => {
+                app.on_focus_changed(focused);
+            }
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { .. }, .. } => {
+                app.reset_blink();
+                app.input_since_frame = true;
             }
             _ => {}
         }
 
         if input.update(&event) {
             if input.close_requested() {
+                app.save_geometry();
                 *control_flow = ControlFlow::Exit;
                 return;
             }
 
+            if let Some(scale_factor) = input.scale_factor_changed() {
+                app.on_scale_factor_changed(scale_factor);
+            }
+
             if let Some(size) = input.window_resized() {
                 let _ = app.pixels.resize_surface(size.width, size.height);
                 let _ = app.pixels.resize_buffer(size.width, size.height);
-                let cols = (size.width as f32 / app.renderer.char_width) as usize;
-                let rows = (size.height as f32 / app.renderer.char_height) as usize;
-                if cols > 0 && rows > 0 {
-                    app.terminal.resize(cols, rows);
-                    let _ = app.pty.resize(rows as u16, cols as u16);
-                }
-                app.window.request_redraw();
+                app.relayout(size.width, size.height);
             }
 
             app.handle_input(&input);
-            if app.terminal.scroll_offset > 0 || input.held_shift() || app.is_selecting {
-                app.window.request_redraw();
-            }
         }
     });
 }
\ No newline at end of file
@@ -0,0 +1,25 @@
+// RoseTerm as a library. The terminal engine (parser, grid, config) builds
+// on its own; the window, renderer and pty backend need the `gui` feature.
+//
+//     let mut term = roseterm::Terminal::new(80, 24);
+//     term.feed(b"\x1b[1mhello\x1b[0m");
+//     assert_eq!(term.get_visible_row(0)[0].char, 'h');
+//     assert!(term.screen_to_string().starts_with("hello\n"));
+
+pub mod config;
+pub mod terminal;
+
+#[cfg(feature = "gui")]
+pub mod backend;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(feature = "gui")]
+pub mod renderer;
+
+pub use config::settings::Config;
+pub use terminal::grid::{Attrs, Cell, Color, CursorShape, Rgb, Terminal, UnderlineStyle};
+
+// Drawing a terminal into an RGBA buffer: `FontRenderer::new`, then
+// `window_size` / `draw`
+#[cfg(feature = "gui")]
+pub use renderer::font::{FontRenderer, Rect};
@@ -1,11 +1,28 @@
-mod backend;
-mod gui;
-mod terminal; // Add this
-mod renderer; // Add this
-
 use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+
+use roseterm::backend::pty::SpawnOptions;
+use roseterm::gui;
+
+#[derive(Parser)]
+#[command(version, about = "The Terminal you have absolutely no reason to use.")]
+struct Args {
+    /// Run this command (and its arguments) instead of $SHELL
+    #[arg(short = 'e', long = "command", num_args = 1.., allow_hyphen_values = true)]
+    command: Option<Vec<String>>,
+
+    /// Start the shell in this directory
+    #[arg(long)]
+    working_directory: Option<PathBuf>,
+}
 
 fn main() -> Result<()> {
     env_logger::init();
-    gui::window::run()
-}
\ No newline at end of file
+    let args = Args::parse();
+    gui::window::run(SpawnOptions {
+        command: args.command,
+        working_directory: args.working_directory,
+        env: Vec::new(),
+    })
+}
@@ -1,155 +1,805 @@
 use anyhow::Result;
+use log::warn;
 use fontdue::{Font, FontSettings};
-use crate::terminal::grid::{Terminal, Color};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crate::config::settings::Config;
+use crate::renderer::theme::{Rgb, Theme};
+use crate::terminal::grid::{is_wide, Attrs, Cell, Terminal, Color, CursorShape, UnderlineStyle};
+
+pub const MIN_FONT_SIZE: f32 = 6.0;
+pub const MAX_FONT_SIZE: f32 = 72.0;
+
+// Tried in order when no font is configured (or it can't be loaded)
+const FONT_SEARCH_PATHS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/gnu-free/FreeMono.ttf",
+];
+
+// Limits for the line_height multiplier
+const MIN_LINE_HEIGHT: f32 = 0.5;
+const MAX_LINE_HEIGHT: f32 = 3.0;
+
+// Resolution of the linear -> sRGB lookup table
+const LINEAR_STEPS: usize = 4096;
+
+struct GammaTables {
+    to_linear: [f32; 256],
+    to_srgb: Vec<u8>,
+}
+
+fn gamma_tables() -> &'static GammaTables {
+    static TABLES: OnceLock<GammaTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut to_linear = [0.0; 256];
+        for (i, v) in to_linear.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *v = if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+        }
+        let to_srgb = (0..LINEAR_STEPS)
+            .map(|i| {
+                let l = i as f32 / (LINEAR_STEPS - 1) as f32;
+                let c = if l <= 0.0031308 { l * 12.92 } else { 1.055 * l.powf(1.0 / 2.4) - 0.055 };
+                (c * 255.0).round() as u8
+            })
+            .collect();
+        GammaTables { to_linear, to_srgb }
+    })
+}
+
+// Mixes one channel of `src` over `dst` with the given coverage
+fn blend(src: u8, dst: u8, alpha: f32, linear: bool) -> u8 {
+    if !linear {
+        return (src as f32 * alpha + dst as f32 * (1.0 - alpha)) as u8;
+    }
+    let tables = gamma_tables();
+    let l = tables.to_linear[src as usize] * alpha + tables.to_linear[dst as usize] * (1.0 - alpha);
+    tables.to_srgb[(l * (LINEAR_STEPS - 1) as f32).round() as usize]
+}
+
+// A region of the window in physical pixels, e.g. one split pane
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// The color halfway between `a` and `b`
+fn mix(a: Rgb, b: Rgb) -> Rgb {
+    let half = |x: u8, y: u8| ((x as u16 + y as u16) / 2) as u8;
+    (half(a.0, b.0), half(a.1, b.1), half(a.2, b.2))
+}
+
+fn load_font(path: &Path) -> std::result::Result<Font, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    Font::from_bytes(data, FontSettings::default()).map_err(|e| e.to_string())
+}
+
+// The styled sibling of a font file that sits next to it, e.g.
+// DejaVuSansMono.ttf -> DejaVuSansMono-Bold.ttf or
+// LiberationMono-Regular.ttf -> LiberationMono-Bold.ttf
+fn sibling_face(path: &Path, suffixes: &[&str]) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let stem = stem.strip_suffix("-Regular").unwrap_or(stem);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("ttf");
+    suffixes.iter()
+        .map(|suffix| path.with_file_name(format!("{}-{}.{}", stem, suffix, extension)))
+        .find(|candidate| candidate.exists())
+}
+
+// The face a cell's text is drawn with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontStyle {
+    fn of(attrs: Attrs) -> Self {
+        match (attrs.contains(Attrs::BOLD), attrs.contains(Attrs::ITALIC)) {
+            (false, false) => FontStyle::Regular,
+            (true, false) => FontStyle::Bold,
+            (false, true) => FontStyle::Italic,
+            (true, true) => FontStyle::BoldItalic,
+        }
+    }
+
+    fn bold(self) -> bool {
+        matches!(self, FontStyle::Bold | FontStyle::BoldItalic)
+    }
+
+    fn italic(self) -> bool {
+        matches!(self, FontStyle::Italic | FontStyle::BoldItalic)
+    }
+}
+
+// A font to rasterize a glyph with, and the styling it can't provide itself
+struct GlyphFace<'a> {
+    font: &'a Font,
+    size: f32,
+    // Strike the glyph twice, 1px apart, for bold without a bold face
+    embolden: bool,
+    // Lean the glyph right, for italic without an italic face
+    slant: bool,
+}
+
+// Horizontal shift per pixel of height for synthetic italic (about 11°)
+const SLANT: f32 = 0.2;
 
 pub struct FontRenderer {
     font: Font,
+    // Styled faces from the config or found next to the main font; missing
+    // bold is drawn by double-striking the regular glyph
+    bold: Option<Font>,
+    italic: Option<Font>,
+    bold_italic: Option<Font>,
+    // Tried for characters the main font has no glyph for
+    fallback: Option<Font>,
+    // Logical size from the config or zoom keys; glyphs are rasterized at
+    // font_size * scale_factor physical pixels
+    pub font_size: f32,
+    pub scale_factor: f32,
+    px_size: f32,
     pub char_width: f32,
     pub char_height: f32,
+    line_height: f32,
+    // Distance from the top of a cell to the glyph baseline
+    baseline: f32,
+    gamma_correct: bool,
+    // Draw SGR 8 text anyway instead of leaving it blank
+    reveal_concealed: bool,
+    // Alpha written for default-background pixels
+    background_alpha: u8,
+    // Blank space between the window edge and the grid, in physical pixels
+    // (x, y), scaled from the logical config value
+    pub padding: (u32, u32),
+    logical_padding: (u32, u32),
+    // A one-row tab bar above the grid, shown while there are several tabs
+    pub tab_bar: bool,
+    // The pane being drawn; cell positions are relative to it
+    area: Rect,
+    // Smooth scrolling moves the grid's pixels down by `y_shift` and keeps
+    // them between the `clip` rows, so a partly scrolled line is cut cleanly
+    y_shift: i32,
+    clip: Option<(i32, i32)>,
+    pub theme: Theme,
+    // A drawn cell has SGR 5 blink; the window clears this before each frame
+    pub blink_visible: bool,
 }
 
 impl FontRenderer {
-    pub fn new() -> Result<Self> {
-        let font_data = std::fs::read("/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf")
-            .or_else(|_| std::fs::read("/usr/share/fonts/liberation/LiberationMono-Regular.ttf"))
-            .or_else(|_| std::fs::read("/usr/share/fonts/gnu-free/FreeMono.ttf"))
-            .expect("Could not find a font file!");
-
-        let font = Font::from_bytes(font_data, FontSettings::default())
-            .map_err(|e| anyhow::anyhow!("Error loading font: {}", e))?;
+    pub fn new(config: &Config, scale_factor: f32) -> Result<Self> {
+        // A configured font that can't be loaded falls back to the search list
+        let candidates = config.font.iter().cloned().chain(FONT_SEARCH_PATHS.iter().map(PathBuf::from));
+        let (font, font_path) = candidates
+            .filter_map(|path| match load_font(&path) {
+                Ok(font) => Some((font, path)),
+                Err(e) => {
+                    if config.font.as_ref() == Some(&path) {
+                        warn!("Could not load font {}: {}", path.display(), e);
+                    }
+                    None
+                }
+            })
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Could not find a font file!"))?;
 
-        let metrics = font.metrics('M', 18.0);
+        let optional_font = |path: Option<PathBuf>, what: &str| {
+            let path = path?;
+            match load_font(&path) {
+                Ok(font) => Some(font),
+                Err(e) => {
+                    warn!("Could not load {} font {}: {}", what, path.display(), e);
+                    None
+                }
+            }
+        };
+        let bold = optional_font(config.bold_font.clone().or_else(|| sibling_face(&font_path, &["Bold"])), "bold");
+        let italic = optional_font(
+            config.italic_font.clone().or_else(|| sibling_face(&font_path, &["Italic", "Oblique"])),
+            "italic",
+        );
+        let bold_italic = optional_font(
+            config.bold_italic_font.clone().or_else(|| sibling_face(&font_path, &["BoldItalic", "BoldOblique"])),
+            "bold italic",
+        );
+        let fallback = optional_font(config.fallback_font.clone(), "fallback");
 
-        Ok(Self {
+        let mut renderer = Self {
             font,
-            char_width: metrics.advance_width,
-            char_height: 22.0,
-        })
+            bold,
+            italic,
+            bold_italic,
+            fallback,
+            font_size: 0.0,
+            scale_factor,
+            px_size: 0.0,
+            char_width: 0.0,
+            char_height: 0.0,
+            line_height: config.line_height.clamp(MIN_LINE_HEIGHT, MAX_LINE_HEIGHT),
+            baseline: 0.0,
+            gamma_correct: config.gamma_correct,
+            reveal_concealed: config.reveal_concealed,
+            background_alpha: (config.background_opacity.clamp(0.0, 1.0) * 255.0).round() as u8,
+            padding: (0, 0),
+            logical_padding: config.padding,
+            tab_bar: false,
+            area: Rect::default(),
+            y_shift: 0,
+            clip: None,
+            blink_visible: false,
+            theme: Theme::from_config(&config.colors),
+        };
+        renderer.set_font_size(config.font_size);
+        Ok(renderer)
+    }
+
+    // Recomputes the cell metrics for a new point size. The line height comes
+    // from the font's ascent, descent and line gap, times `line_height`; any
+    // extra (or missing) space is split evenly above and below the glyphs.
+    pub fn set_font_size(&mut self, size: f32) {
+        let size = size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        let px_size = size * self.scale_factor;
+        // Fonts without horizontal metrics get the old 18/22/16 proportions
+        let (ascent, descent, line_gap) = match self.font.horizontal_line_metrics(px_size) {
+            Some(line) => (line.ascent, -line.descent, line.line_gap),
+            None => (px_size * 16.0 / 18.0, px_size * 6.0 / 18.0, 0.0),
+        };
+        self.font_size = size;
+        self.px_size = px_size;
+        // The widest printable ASCII advance, so no glyph of a font whose
+        // 'M' happens to be narrow spills into the next cell
+        self.char_width = (0x21u8..0x7f)
+            .map(|c| self.font.metrics(c as char, px_size).advance_width)
+            .fold(0.0, f32::max);
+        self.char_height = ((ascent + descent + line_gap) * self.line_height).round().max(1.0);
+        self.baseline = (ascent + (self.char_height - ascent - descent) / 2.0).round();
+        let scale = |v: u32| (v as f32 * self.scale_factor).round() as u32;
+        self.padding = (scale(self.logical_padding.0), scale(self.logical_padding.1));
+    }
+
+    // The window moved to a monitor with a different DPI
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.set_font_size(self.font_size);
+    }
+
+    // Height of the tab bar, or 0 when it's hidden
+    fn tab_bar_height(&self) -> u32 {
+        if self.tab_bar { self.char_height.ceil() as u32 } else { 0 }
+    }
+
+    // The part of the window below the tab bar, shared out between panes
+    pub fn content_rect(&self, width: u32, height: u32) -> Rect {
+        let bar = self.tab_bar_height().min(height);
+        Rect { x: 0, y: bar, width, height: height - bar }
+    }
+
+    // Top-left pixel of a cell in the pane being drawn
+    fn cell_origin(&self, col: usize, row: usize) -> (usize, usize) {
+        let (left, top) = self.grid_offset(self.area);
+        let x = (self.area.x + left) as usize + (col as f32 * self.char_width) as usize;
+        let y = (self.area.y + top) as usize + (row as f32 * self.char_height) as usize;
+        (x, y)
+    }
+
+    // Where the grid starts inside a pane: past the padding, plus half of
+    // the pixels left over after the last whole cell, so the grid sits in
+    // the middle with even margins
+    fn grid_offset(&self, area: Rect) -> (u32, u32) {
+        let (width, height) = self.text_area(area.width, area.height);
+        let (cols, rows) = self.grid_size(area.width, area.height);
+        let spare_x = (width as f32 - cols as f32 * self.char_width).max(0.0);
+        let spare_y = (height as f32 - rows as f32 * self.char_height).max(0.0);
+        (self.padding.0 + (spare_x / 2.0) as u32, self.padding.1 + (spare_y / 2.0) as u32)
+    }
+
+    // The part of a pane of this size that is inside the padding
+    pub fn text_area(&self, width: u32, height: u32) -> (u32, u32) {
+        (width.saturating_sub(self.padding.0 * 2), height.saturating_sub(self.padding.1 * 2))
+    }
+
+    // How many columns and rows fit in a window of this size
+    pub fn grid_size(&self, width: u32, height: u32) -> (usize, usize) {
+        let (width, height) = self.text_area(width, height);
+        ((width as f32 / self.char_width) as usize, (height as f32 / self.char_height) as usize)
+    }
+
+    // The window size that holds exactly `cols` x `rows` cells in a single pane
+    pub fn window_size(&self, cols: usize, rows: usize) -> (u32, u32) {
+        let width = (cols as f32 * self.char_width).ceil() as u32 + self.padding.0 * 2;
+        let height = (rows as f32 * self.char_height).ceil() as u32 + self.padding.1 * 2 + self.tab_bar_height();
+        (width, height)
+    }
+
+    // The cell of the pane at `area` under a window position; the margins
+    // map to the nearest edge cell
+    pub fn pixel_to_cell(&self, area: Rect, x: f32, y: f32) -> (usize, usize) {
+        let (left, top) = self.grid_offset(area);
+        let left = (area.x + left) as f32;
+        let top = (area.y + top) as f32;
+        let (cols, rows) = self.grid_size(area.width, area.height);
+        let col = ((x - left) / self.char_width).max(0.0) as usize;
+        let row = ((y - top) / self.char_height).max(0.0) as usize;
+        (col.min(cols.saturating_sub(1)), row.min(rows.saturating_sub(1)))
+    }
+
+    // Theme colors plus any OSC 4/10/11 overrides the terminal has received.
+    // Reverse screen mode (DECSCNM) trades the two defaults, so per-cell
+    // inverse on top of it cancels out.
+    fn color_to_rgb(&self, term: &Terminal, color: Color) -> Rgb {
+        let color = match (term.reverse_screen, color) {
+            (true, Color::DefaultFg) => Color::DefaultBg,
+            (true, Color::DefaultBg) => Color::DefaultFg,
+            _ => color,
+        };
+        term.resolve_color(color)
+    }
+
+    fn fill_cell(&self, frame: &mut [u8], screen_width: u32, col: usize, row: usize, color: Rgb) {
+        let (cx, cy) = self.cell_origin(col, row);
+        let cw = self.char_width.ceil() as usize;
+        let ch = self.char_height.ceil() as usize;
+        self.fill_rect(frame, screen_width, cx, cy, cw, ch, color);
+    }
+
+    // Where a row of pixels lands after the smooth-scroll shift, or None if
+    // that is outside the clip
+    fn shifted_y(&self, y: i32) -> Option<usize> {
+        let y = y + self.y_shift;
+        match self.clip {
+            Some((top, bottom)) if y < top || y >= bottom => None,
+            _ => usize::try_from(y).ok(),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fill_rect(&self, frame: &mut [u8], screen_width: u32, x0: usize, y0: usize, w: usize, h: usize, color: Rgb) {
+        let (r, g, b) = color;
+        for y in y0..(y0+h) {
+            let Some(y) = self.shifted_y(y as i32) else { continue };
+            for x in x0..(x0+w) {
+                if x >= screen_width as usize { continue; }
+                let idx = (y * screen_width as usize + x) * 4;
+                if idx + 3 < frame.len() {
+                    frame[idx] = r;
+                    frame[idx+1] = g;
+                    frame[idx+2] = b;
+                    frame[idx+3] = 255;
+                }
+            }
+        }
+    }
+
+    // Horizontal line across a cell, `y_offset` pixels below the cell top
+    fn draw_hline(&self, frame: &mut [u8], screen_width: u32, col: usize, row: usize, y_offset: usize, color: Rgb) {
+        let (cx, cy) = self.cell_origin(col, row);
+        let y = cy + y_offset;
+        let cw = self.char_width.ceil() as usize;
+        self.fill_rect(frame, screen_width, cx, y, cw, 1, color);
+    }
+
+    // Picks the font that actually has a glyph for `c` in the wanted style,
+    // along with the size to rasterize it at. Without a bold or italic face
+    // the glyph is emboldened or slanted instead. Fallback glyphs wider than their cells
+    // (two for wide characters) are scaled down so they never spill into the next column.
+    fn glyph_face(&self, c: char, style: FontStyle) -> Option<GlyphFace<'_>> {
+        let has = |f: &&Font| f.lookup_glyph_index(c) != 0;
+        let styled = match style {
+            FontStyle::Regular => None,
+            FontStyle::Bold => self.bold.as_ref().filter(has).map(|font| (font, false)),
+            FontStyle::Italic => self.italic.as_ref().filter(has).map(|font| (font, false)),
+            // A bold face alone still needs slanting
+            FontStyle::BoldItalic => self.bold_italic.as_ref().filter(has).map(|font| (font, false))
+                .or_else(|| self.bold.as_ref().filter(has).map(|font| (font, true))),
+        };
+        if let Some((font, slant)) = styled {
+            return Some(GlyphFace { font, size: self.px_size, embolden: false, slant });
+        }
+        let (embolden, slant) = (style.bold(), style.italic());
+        if self.font.lookup_glyph_index(c) != 0 {
+            return Some(GlyphFace { font: &self.font, size: self.px_size, embolden, slant });
+        }
+        let fallback = self.fallback.as_ref().filter(|f| f.lookup_glyph_index(c) != 0)?;
+        let advance = fallback.metrics(c, self.px_size).advance_width;
+        let room = if is_wide(c) { self.char_width * 2.0 } else { self.char_width };
+        let size = if advance > room {
+            self.px_size * room / advance
+        } else {
+            self.px_size
+        };
+        Some(GlyphFace { font: fallback, size, embolden, slant })
     }
 
-    fn color_to_rgb(&self, color: Color) -> (u8, u8, u8) {
-        match color {
-            Color::Black => (0, 0, 0),
-            Color::Red => (205, 49, 49),
-            Color::Green => (13, 188, 121),
-            Color::Yellow => (229, 229, 16),
-            Color::Blue => (36, 114, 200),
-            Color::Magenta => (188, 63, 188),
-            Color::Cyan => (17, 168, 205),
-            Color::White => (229, 229, 229),
+    // Outline box for characters no loaded font can render
+    fn draw_missing_glyph(&self, frame: &mut [u8], screen_width: u32, cx: usize, cy: usize, color: Rgb) {
+        let (x0, y0) = (cx + 1, cy + 2);
+        let w = (self.char_width as usize).saturating_sub(2).max(2);
+        let h = (self.char_height as usize).saturating_sub(4).max(2);
+        self.fill_rect(frame, screen_width, x0, y0, w, 1, color);
+        self.fill_rect(frame, screen_width, x0, y0 + h - 1, w, 1, color);
+        self.fill_rect(frame, screen_width, x0, y0, 1, h, color);
+        self.fill_rect(frame, screen_width, x0 + w - 1, y0, 1, h, color);
+    }
 
-            Color::BrightBlack => (102, 102, 102),
-            Color::BrightRed => (241, 76, 76),
-            Color::BrightGreen => (35, 209, 139),
-            Color::BrightYellow => (245, 245, 67),
-            Color::BrightBlue => (59, 142, 234),
-            Color::BrightMagenta => (214, 112, 214),
-            Color::BrightCyan => (41, 184, 219),
-            Color::BrightWhite => (255, 255, 255),
+    fn draw_underline(&self, frame: &mut [u8], screen_width: u32, col: usize, row: usize, style: UnderlineStyle, color: Rgb) {
+        let (cx, cy) = self.cell_origin(col, row);
+        let cw = self.char_width.ceil() as usize;
+        let last = self.char_height as usize - 1;
+        let y = cy + (self.baseline as usize + 2).min(last);
+        match style {
+            UnderlineStyle::None => {}
+            UnderlineStyle::Single => self.fill_rect(frame, screen_width, cx, y, cw, 1, color),
+            UnderlineStyle::Double => {
+                let y = cy + (self.baseline as usize + 1).min(last.saturating_sub(2));
+                self.fill_rect(frame, screen_width, cx, y, cw, 1, color);
+                self.fill_rect(frame, screen_width, cx, y + 2, cw, 1, color);
+            }
+            UnderlineStyle::Curly => {
+                // One full sine period per cell so neighbouring cells join up
+                let y = y.min(cy + last.saturating_sub(2));
+                for dx in 0..cw {
+                    let phase = dx as f32 / cw as f32 * std::f32::consts::TAU;
+                    let dy = (phase.sin() * 1.5).round() as isize;
+                    let py = (y as isize - dy).max(cy as isize) as usize;
+                    self.fill_rect(frame, screen_width, cx + dx, py, 1, 1, color);
+                }
+            }
+            UnderlineStyle::Dotted => {
+                for dx in (0..cw).step_by(2) {
+                    self.fill_rect(frame, screen_width, cx + dx, y, 1, 1, color);
+                }
+            }
+            UnderlineStyle::Dashed => {
+                // A dash over the middle of each cell leaves gaps at the edges
+                let gap = cw / 4;
+                self.fill_rect(frame, screen_width, cx + gap, y, cw - 2 * gap, 1, color);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph(&self, frame: &mut [u8], screen_width: u32, col: usize, row: usize, c: char, color: Rgb, style: FontStyle) {
+        let (cx, cy) = self.cell_origin(col, row);
+        self.draw_glyph_at(frame, screen_width, cx, cy, c, color, style);
+    }
 
-            Color::DefaultFg => (229, 229, 229),
-            Color::DefaultBg => (16, 16, 24),
+    // A cell's combining marks, centred over its character. Fonts position
+    // marks relative to very different pen positions, so their own offsets
+    // aren't used. Marks no font has (zero-width joiners) draw nothing.
+    fn draw_marks(&self, frame: &mut [u8], screen_width: u32, col: usize, row: usize, cell: &Cell, color: Rgb) {
+        let (cx, cy) = self.cell_origin(col, row);
+        let room = if is_wide(cell.char) { self.char_width * 2.0 } else { self.char_width };
+        let style = FontStyle::of(cell.attrs);
+        for mark in cell.marks.into_iter().filter(|&mark| mark != '\0') {
+            let Some(face) = self.glyph_face(mark, style) else { continue };
+            let metrics = face.font.metrics(mark, face.size);
+            let left = cx as f32 + (room - metrics.width as f32) / 2.0 - metrics.xmin as f32;
+            self.draw_glyph_at(frame, screen_width, left.max(0.0) as usize, cy, mark, color, style);
         }
     }
 
-    pub fn draw(&self, term: &Terminal, frame: &mut [u8], screen_width: u32) {
-        let (bg_r, bg_g, bg_b) = self.color_to_rgb(Color::DefaultBg);
-        for pixel in frame.chunks_exact_mut(4) {
-            pixel.copy_from_slice(&[bg_r, bg_g, bg_b, 255]);
+    // Draws `c` in the cell-sized box whose top-left pixel is (cx, cy)
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph_at(&self, frame: &mut [u8], screen_width: u32, cx: usize, cy: usize, c: char, color: Rgb, style: FontStyle) {
+        if c == '\0' || c.is_whitespace() { return; }
+
+        let Some(face) = self.glyph_face(c, style) else {
+            self.draw_missing_glyph(frame, screen_width, cx, cy, color);
+            return;
+        };
+        let (metrics, bitmap) = face.font.rasterize(c, face.size);
+        let strikes = if face.embolden { 2 } else { 1 };
+        if metrics.width == 0 || metrics.height == 0 { return; }
+
+        let (cell_x_start, cell_y_start) = (cx as i32, cy as i32);
+        let baseline_y = cell_y_start + self.baseline as i32;
+        let (fg_r, fg_g, fg_b) = color;
+        // Shear about the cell's middle row so the glyph leans without
+        // leaving its column
+        let slant_center = cell_y_start + self.char_height as i32 / 2;
+
+        for (i, coverage) in bitmap.into_iter().enumerate() {
+            let x_in_bitmap = (i % metrics.width) as i32;
+            let y_in_bitmap = (i / metrics.width) as i32;
+            let y_offset_from_baseline = -(metrics.ymin + metrics.height as i32) + y_in_bitmap;
+
+            let y = baseline_y + y_offset_from_baseline;
+            let Some(y) = self.shifted_y(y) else { continue };
+            let shear = if face.slant { ((slant_center - y as i32 + self.y_shift) as f32 * SLANT).round() as i32 } else { 0 };
+
+            for strike in 0..strikes {
+                let x = cell_x_start + x_in_bitmap + metrics.xmin + shear + strike;
+                if x < 0 || x >= screen_width as i32 { continue; }
+
+                let idx = (y * screen_width as usize + x as usize) * 4;
+
+                if idx + 3 < frame.len() {
+                    if coverage == 0 { continue; }
+                    let alpha = coverage as f32 / 255.0;
+
+                    frame[idx] = blend(fg_r, frame[idx], alpha, self.gamma_correct);
+                    frame[idx+1] = blend(fg_g, frame[idx+1], alpha, self.gamma_correct);
+                    frame[idx+2] = blend(fg_b, frame[idx+2], alpha, self.gamma_correct);
+                    // Text stays opaque even over a translucent background
+                    frame[idx+3] = frame[idx+3].max(coverage);
+                }
+            }
+        }
+    }
+
+    // Draws a terminal into the pane at `area`. The cursor is hollow unless
+    // the window `has_focus`; `blink_on` is the visible phase for SGR 5
+    // text. A `scroll_fraction` between 0 and 1 shows the view that much of
+    // a line further back in the scrollback.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(&mut self, term: &Terminal, frame: &mut [u8], screen_width: u32, area: Rect, cursor_on: bool, has_focus: bool, blink_on: bool, scroll_fraction: f32) {
+        self.area = area;
+        let default_bg = self.color_to_rgb(term, Color::DefaultBg);
+        let (bg_r, bg_g, bg_b) = default_bg;
+        for y in area.y..area.y + area.height {
+            let start = (y * screen_width + area.x) as usize * 4;
+            let end = (start + area.width as usize * 4).min(frame.len());
+            if start >= end { break; }
+            for pixel in frame[start..end].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[bg_r, bg_g, bg_b, self.background_alpha]);
+            }
+        }
+
+        let shift = (scroll_fraction.clamp(0.0, 1.0) * self.char_height).round() as i32;
+        let top_line = term.visible_line(0);
+        let smooth = shift > 0 && top_line > term.history_start;
+        if smooth {
+            let top = self.cell_origin(0, 0).1 as i32;
+            let bottom = self.cell_origin(0, term.rows).1 as i32;
+            self.clip = Some((top, bottom));
+            // The line above the screen, partly scrolled into view
+            if let Some(cells) = term.line_at_absolute(top_line - 1) {
+                self.y_shift = shift - self.char_height as i32;
+                self.draw_row(term, frame, screen_width, 0, top_line - 1, cells, default_bg, blink_on);
+            }
+            self.y_shift = shift;
         }
 
         for row_idx in 0..term.rows {
             let row = term.get_visible_row(row_idx);
+            self.blink_visible |= row.iter().any(|cell| cell.attrs.contains(Attrs::BLINK));
+            self.draw_row(term, frame, screen_width, row_idx, term.visible_line(row_idx), row, default_bg, blink_on);
+        }
+
+        if term.scroll_offset == 0 && cursor_on && term.cursor_visible {
+            self.draw_cursor(term, frame, screen_width, default_bg, !has_focus);
+        }
+        self.y_shift = 0;
+        self.clip = None;
+
+        if term.scroll_offset > 0 || smooth {
+            self.draw_scrollbar(term, frame, screen_width);
+        }
+    }
+
+    // One row of cells, drawn at screen row `row_idx`; `line` is its
+    // absolute line, for the selection and search highlights
+    #[allow(clippy::too_many_arguments)]
+    fn draw_row(&self, term: &Terminal, frame: &mut [u8], screen_width: u32, row_idx: usize, line: usize, row: &[Cell], default_bg: Rgb, blink_on: bool) {
+        for (col_idx, cell) in row.iter().enumerate() {
 
-            for (col_idx, cell) in row.iter().enumerate() {
-
-                // NEW: Handle Selection Highlighting
-                // If selected, override colors to White on Grey
-                let (fg, bg) = if term.is_selected(col_idx, row_idx) {
-                    (Color::Black, Color::BrightWhite) // High contrast selection
-                } else if cell.inverse {
-                    (cell.bg, cell.fg)
-                } else {
-                    (cell.fg, cell.bg)
-                };
-
-                if bg != Color::DefaultBg {
-                     let (br, bg, bb) = self.color_to_rgb(bg);
-                     let cx = (col_idx as f32 * self.char_width) as usize;
-                     let cy = (row_idx as f32 * self.char_height) as usize;
-                     let cw = self.char_width.ceil() as usize;
-                     let ch = self.char_height.ceil() as usize;
-
-                     for y in cy..(cy+ch) {
-                         for x in cx..(cx+cw) {
-                             if x >= screen_width as usize { continue; }
-                             let idx = (y * screen_width as usize + x) * 4;
-                             if idx + 3 < frame.len() {
-                                 frame[idx] = br;
-                                 frame[idx+1] = bg;
-                                 frame[idx+2] = bb;
-                                 frame[idx+3] = 255;
-                             }
-                         }
-                     }
+            // Selection uses the theme's highlight colors
+            let (fg, bg) = if term.is_selected(col_idx, line) {
+                (self.theme.selection_fg, self.theme.selection_bg)
+            } else if let Some(current) = term.search_highlight(col_idx, line) {
+                let bg = if current { self.theme.search_current_bg } else { self.theme.search_bg };
+                (self.theme.search_fg, bg)
+            } else {
+                let fg = if cell.attrs.contains(Attrs::BOLD) { cell.fg.bright() } else { cell.fg };
+                let (fg, bg) = (self.color_to_rgb(term, fg), self.color_to_rgb(term, cell.bg));
+                let (fg, bg) = if cell.attrs.contains(Attrs::INVERSE) { (bg, fg) } else { (fg, bg) };
+                // Faint text sits halfway between its color and the background
+                if cell.attrs.contains(Attrs::DIM) { (mix(fg, bg), bg) } else { (fg, bg) }
+            };
+
+            // A wide character's cells are filled together, before its glyph
+            // is drawn across them
+            if bg != default_bg && cell.char != '\0' {
+                self.fill_cell(frame, screen_width, col_idx, row_idx, bg);
+                if row.get(col_idx + 1).is_some_and(|next| next.char == '\0') {
+                    self.fill_cell(frame, screen_width, col_idx + 1, row_idx, bg);
                 }
+            }
 
-                if cell.char == '\0' || cell.char == ' ' { continue; }
+            let concealed = cell.attrs.contains(Attrs::HIDDEN) && !self.reveal_concealed;
+            if (blink_on || !cell.attrs.contains(Attrs::BLINK)) && !concealed {
+                self.draw_glyph(frame, screen_width, col_idx, row_idx, cell.char, fg, FontStyle::of(cell.attrs));
+                self.draw_marks(frame, screen_width, col_idx, row_idx, cell, fg);
+            }
 
-                let (metrics, bitmap) = self.font.rasterize(cell.char, 18.0);
-                if metrics.width == 0 || metrics.height == 0 { continue; }
+            if cell.underline != UnderlineStyle::None {
+                let color = cell.underline_color.map_or(fg, |c| self.color_to_rgb(term, c));
+                self.draw_underline(frame, screen_width, col_idx, row_idx, cell.underline, color);
+            }
 
-                let cell_x_start = (col_idx as f32 * self.char_width) as i32;
-                let cell_y_start = (row_idx as f32 * self.char_height) as i32;
-                let baseline_y = cell_y_start + 16;
-                let (fg_r, fg_g, fg_b) = self.color_to_rgb(fg);
+            if cell.attrs.contains(Attrs::STRIKE) {
+                let middle = self.char_height as usize / 2;
+                self.draw_hline(frame, screen_width, col_idx, row_idx, middle, fg);
+            }
 
-                for (i, coverage) in bitmap.into_iter().enumerate() {
-                    let x_in_bitmap = (i % metrics.width) as i32;
-                    let y_in_bitmap = (i / metrics.width) as i32;
-                    let y_offset_from_baseline = -(metrics.ymin + metrics.height as i32) + y_in_bitmap;
+            if cell.attrs.contains(Attrs::OVERLINE) {
+                self.draw_hline(frame, screen_width, col_idx, row_idx, 0, fg);
+            }
 
-                    let x = cell_x_start + x_in_bitmap + metrics.xmin;
-                    let y = baseline_y + y_offset_from_baseline;
+            if cell.link.is_some() && cell.link == term.hovered_link {
+                let underline_y = (self.baseline as usize + 2).min(self.char_height as usize - 1);
+                self.draw_hline(frame, screen_width, col_idx, row_idx, underline_y, fg);
+            }
+        }
+    }
 
-                    if x < 0 || x >= screen_width as i32 || y < 0 { continue; }
+    // Translucent thumb on the pane's right edge showing which part of the
+    // scrollback is on screen
+    fn draw_scrollbar(&self, term: &Terminal, frame: &mut [u8], screen_width: u32) {
+        let area_height = self.area.height as usize;
+        let total = term.total_lines() as f32;
+        let top = (term.visible_line(0) - term.history_start) as f32 / total;
+        let thumb = term.rows as f32 / total;
 
-                    let idx = (y as usize * screen_width as usize + x as usize) * 4;
+        let w = (4.0 * self.scale_factor).round().max(1.0) as usize;
+        let right = (self.area.x + self.area.width).min(screen_width) as usize;
+        let x0 = right.saturating_sub(w);
+        let y0 = self.area.y as usize + (top * area_height as f32) as usize;
+        let h = ((thumb * area_height as f32) as usize).max(w * 2);
+        let bottom = (self.area.y as usize + area_height).min(frame.len() / 4 / screen_width.max(1) as usize);
 
-                    if idx + 3 < frame.len() {
-                        let alpha = coverage as f32 / 255.0;
-                        let inv_alpha = 1.0 - alpha;
+        let (r, g, b) = self.theme.foreground;
+        for y in y0..(y0 + h).min(bottom) {
+            for x in x0..right {
+                let idx = (y * screen_width as usize + x) * 4;
+                frame[idx] = blend(r, frame[idx], 0.4, self.gamma_correct);
+                frame[idx+1] = blend(g, frame[idx+1], 0.4, self.gamma_correct);
+                frame[idx+2] = blend(b, frame[idx+2], 0.4, self.gamma_correct);
+                frame[idx+3] = 255;
+            }
+        }
+    }
 
-                        let current_r = frame[idx] as f32;
-                        let current_g = frame[idx+1] as f32;
-                        let current_b = frame[idx+2] as f32;
+    // One-line prompt drawn over a row of the last pane drawn (the search
+    // query input)
+    pub fn draw_status_line(&self, frame: &mut [u8], screen_width: u32, row: usize, text: &str) {
+        let (_, y) = self.cell_origin(0, row);
+        let (x, width) = (self.area.x as usize, self.area.width as usize);
+        self.fill_rect(frame, screen_width, x, y, width, self.char_height.ceil() as usize, self.theme.selection_bg);
+        let cols = (self.area.width.saturating_sub(self.padding.0 * 2) as f32 / self.char_width) as usize;
+        for (col, c) in text.chars().take(cols).enumerate() {
+            self.draw_glyph(frame, screen_width, col, row, c, self.theme.selection_fg, FontStyle::Regular);
+        }
+    }
 
-                        frame[idx] = (fg_r as f32 * alpha + current_r * inv_alpha) as u8;
-                        frame[idx+1] = (fg_g as f32 * alpha + current_g * inv_alpha) as u8;
-                        frame[idx+2] = (fg_b as f32 * alpha + current_b * inv_alpha) as u8;
-                        frame[idx+3] = 255;
-                    }
-                }
+    // The line between two split panes
+    pub fn draw_divider(&self, frame: &mut [u8], screen_width: u32, rect: Rect) {
+        let color = mix(self.theme.background, self.theme.foreground);
+        self.fill_rect(frame, screen_width, rect.x as usize, rect.y as usize, rect.width as usize, rect.height as usize, color);
+    }
+
+    // The tab bar along the top of the window: one equal-width label per tab,
+    // with the active one in the selection colors
+    pub fn draw_tab_bar(&self, frame: &mut [u8], screen_width: u32, titles: &[&str], active: usize) {
+        let height = self.tab_bar_height() as usize;
+        let bar_bg = mix(self.theme.background, self.theme.foreground);
+        self.fill_rect(frame, screen_width, 0, 0, screen_width as usize, height, mix(self.theme.background, bar_bg));
+        if titles.is_empty() { return; }
+
+        let tab_width = screen_width as usize / titles.len();
+        for (i, title) in titles.iter().enumerate() {
+            let x0 = i * tab_width;
+            let (fg, bg) = if i == active {
+                (self.theme.selection_fg, self.theme.selection_bg)
+            } else {
+                (self.theme.foreground, self.theme.background)
+            };
+            // Leave a 1px gap between tabs
+            self.fill_rect(frame, screen_width, x0, 0, tab_width.saturating_sub(1), height, bg);
+
+            let label = if title.is_empty() { format!(" {} ", i + 1) } else { format!(" {}: {} ", i + 1, title) };
+            let cols = ((tab_width.saturating_sub(1)) as f32 / self.char_width) as usize;
+            for (col, c) in label.chars().take(cols).enumerate() {
+                let x = x0 + (col as f32 * self.char_width) as usize;
+                self.draw_glyph_at(frame, screen_width, x, 0, c, fg, FontStyle::Regular);
             }
         }
+    }
 
-        if term.scroll_offset == 0 {
-            let cx = (term.cursor_x as f32 * self.char_width) as usize;
-            let cy = (term.cursor_y as f32 * self.char_height) as usize;
-            let cursor_h = self.char_height as usize;
-            let cursor_w = self.char_width as usize;
+    fn draw_cursor(&self, term: &Terminal, frame: &mut [u8], screen_width: u32, default_bg: Rgb, hollow: bool) {
+        let (cx, cy) = self.cell_origin(term.cursor_x, term.cursor_y);
+        let cell_h = self.char_height as usize;
+        let cell_w = self.char_width as usize;
 
-            for y in cy..(cy + cursor_h) {
-                for x in cx..(cx + cursor_w) {
-                    let idx = (y * screen_width as usize + x) * 4;
-                    if idx + 3 < frame.len() {
-                        frame[idx] = 255 - frame[idx];
-                        frame[idx+1] = 255 - frame[idx+1];
-                        frame[idx+2] = 255 - frame[idx+2];
-                        frame[idx+3] = 255;
-                    }
+        // The area covered by the cursor for each shape
+        let (x0, y0, w, h) = match term.cursor_shape {
+            CursorShape::Block => (cx, cy, cell_w, cell_h),
+            CursorShape::Underline => (cx, cy + cell_h.saturating_sub(2), cell_w, 2),
+            CursorShape::Bar => (cx, cy, 2, cell_h),
+        };
+
+        // Hollow: only the 1px outline of the shape
+        let on_edge = |x: usize, y: usize| x == x0 || x + 1 == x0 + w || y == y0 || y + 1 == y0 + h;
+
+        if let Some(cursor_color) = self.theme.cursor {
+            if hollow {
+                self.fill_rect(frame, screen_width, x0, y0, w, 1, cursor_color);
+                self.fill_rect(frame, screen_width, x0, y0 + h - 1, w, 1, cursor_color);
+                self.fill_rect(frame, screen_width, x0, y0, 1, h, cursor_color);
+                self.fill_rect(frame, screen_width, x0 + w - 1, y0, 1, h, cursor_color);
+                return;
+            }
+            self.fill_rect(frame, screen_width, x0, y0, w, h, cursor_color);
+            if term.cursor_shape == CursorShape::Block {
+                // Solid block: redraw the glyph on top of it
+                let cell = term.grid[term.cursor_y].get(term.cursor_x).copied().unwrap_or_default();
+                if !cell.attrs.contains(Attrs::HIDDEN) || self.reveal_concealed {
+                    let text_color = self.theme.cursor_text.unwrap_or(default_bg);
+                    let style = FontStyle::of(cell.attrs);
+                    self.draw_glyph(frame, screen_width, term.cursor_x, term.cursor_y, cell.char, text_color, style);
+                    self.draw_marks(frame, screen_width, term.cursor_x, term.cursor_y, &cell, text_color);
+                }
+            }
+            return;
+        }
+
+        for row in y0..(y0 + h) {
+            let Some(y) = self.shifted_y(row as i32) else { continue };
+            for x in x0..(x0 + w) {
+                if hollow && !on_edge(x, row) { continue; }
+                let idx = (y * screen_width as usize + x) * 4;
+                if idx + 3 < frame.len() {
+                    frame[idx] = 255 - frame[idx];
+                    frame[idx+1] = 255 - frame[idx+1];
+                    frame[idx+2] = 255 - frame[idx+2];
+                    frame[idx+3] = 255;
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws `input` into a cols x 1 terminal and returns the color of the
+    // middle pixel of each cell
+    fn render_cells(input: &[u8], cols: usize) -> Vec<Rgb> {
+        let mut renderer = FontRenderer::new(&Config::default(), 1.0).unwrap();
+        let mut term = Terminal::new(cols, 1);
+        term.base_palette = renderer.theme.palette();
+        term.feed(input);
+
+        let (width, height) = renderer.window_size(cols, 1);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let area = Rect { x: 0, y: 0, width, height };
+        renderer.draw(&term, &mut frame, width, area, false, true, true, 0.0);
+
+        (0..cols).map(|col| {
+            let (x, y) = renderer.cell_origin(col, 0);
+            let x = x + renderer.char_width as usize / 2;
+            let y = y + renderer.char_height as usize / 2;
+            let idx = (y * width as usize + x) * 4;
+            (frame[idx], frame[idx + 1], frame[idx + 2])
+        }).collect()
+    }
+
+    #[test]
+    fn inverse_swaps_foreground_and_background() {
+        let theme = Theme::default();
+        let cells = render_cells(b" \x1b[7m \x1b[38;2;10;20;30m \x1b[27;48;2;40;50;60m ", 4);
+        assert_eq!(cells[0], theme.background);
+        // Default colors swapped: the default foreground becomes the background
+        assert_eq!(cells[1], theme.foreground);
+        // A truecolor foreground becomes the background
+        assert_eq!(cells[2], (10, 20, 30));
+        // Inverse off again: the truecolor background is drawn as is
+        assert_eq!(cells[3], (40, 50, 60));
+    }
+}
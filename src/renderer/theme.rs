@@ -0,0 +1,104 @@
+use log::warn;
+use crate::config::settings::ColorsConfig;
+use crate::terminal::grid::{parse_color_spec, xterm_palette};
+
+pub use crate::terminal::grid::Rgb;
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    // Black, Red, Green, Yellow, Blue, Magenta, Cyan, White, then the bright variants
+    pub ansi: [Rgb; 16],
+    pub foreground: Rgb,
+    pub background: Rgb,
+    // None keeps the classic inverting cursor
+    pub cursor: Option<Rgb>,
+    // Glyph under a solid block cursor; None uses the default background
+    pub cursor_text: Option<Rgb>,
+    pub selection_fg: Rgb,
+    pub selection_bg: Rgb,
+    // Scrollback search matches, and the one currently jumped to
+    pub search_fg: Rgb,
+    pub search_bg: Rgb,
+    pub search_current_bg: Rgb,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            ansi: [
+                (0, 0, 0),
+                (205, 49, 49),
+                (13, 188, 121),
+                (229, 229, 16),
+                (36, 114, 200),
+                (188, 63, 188),
+                (17, 168, 205),
+                (229, 229, 229),
+
+                (102, 102, 102),
+                (241, 76, 76),
+                (35, 209, 139),
+                (245, 245, 67),
+                (59, 142, 234),
+                (214, 112, 214),
+                (41, 184, 219),
+                (255, 255, 255),
+            ],
+            foreground: (229, 229, 229),
+            background: (16, 16, 24),
+            cursor: None,
+            cursor_text: None,
+            selection_fg: (0, 0, 0),
+            selection_bg: (255, 255, 255),
+            search_fg: (0, 0, 0),
+            search_bg: (229, 229, 16),
+            search_current_bg: (241, 140, 40),
+        }
+    }
+}
+
+impl Theme {
+    // The full palette a Terminal draws from, with this theme's ANSI and
+    // default colors in place of xterm's
+    pub fn palette(&self) -> Vec<Rgb> {
+        xterm_palette(&self.ansi, self.foreground, self.background)
+    }
+
+    pub fn from_config(colors: &ColorsConfig) -> Self {
+        let mut theme = Self::default();
+
+        // A set and valid color; invalid ones are logged and left at the default
+        let parse = |value: &Option<String>| -> Option<Rgb> {
+            let text = value.as_ref()?;
+            let rgb = parse_color_spec(text);
+            if rgb.is_none() { warn!("Ignoring invalid color {:?} in config", text); }
+            rgb
+        };
+        let apply = |slot: &mut Rgb, value: &Option<String>| {
+            if let Some(rgb) = parse(value) { *slot = rgb; }
+        };
+
+        let ansi = [
+            &colors.black, &colors.red, &colors.green, &colors.yellow,
+            &colors.blue, &colors.magenta, &colors.cyan, &colors.white,
+            &colors.bright_black, &colors.bright_red, &colors.bright_green, &colors.bright_yellow,
+            &colors.bright_blue, &colors.bright_magenta, &colors.bright_cyan, &colors.bright_white,
+        ];
+        for (slot, value) in theme.ansi.iter_mut().zip(ansi) {
+            apply(slot, value);
+        }
+
+        apply(&mut theme.foreground, &colors.foreground);
+        apply(&mut theme.background, &colors.background);
+        apply(&mut theme.selection_fg, &colors.selection_foreground);
+        apply(&mut theme.selection_bg, &colors.selection_background);
+        apply(&mut theme.search_fg, &colors.search_foreground);
+        apply(&mut theme.search_bg, &colors.search_background);
+        apply(&mut theme.search_current_bg, &colors.search_current_background);
+
+        theme.cursor = parse(&colors.cursor);
+        theme.cursor_text = parse(&colors.cursor_text);
+
+        theme
+    }
+}
@@ -1,4 +1,21 @@
-use vte::{Perform, Params};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, Parser, Perform};
+
+pub type Rgb = (u8, u8, u8);
+
+// Palette slots: 0-255 are the OSC 4 indexed colors, then the OSC 10/11 defaults
+pub const FG_SLOT: usize = 256;
+pub const BG_SLOT: usize = 257;
+pub const PALETTE_SLOTS: usize = 258;
+
+// Same depth as xterm's title stack
+const TITLE_STACK_LIMIT: usize = 10;
+// Hyperlink targets kept before unused ones are first looked for
+const LINK_COMPACT_MIN: usize = 256;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Color {
@@ -6,23 +23,282 @@ pub enum Color {
     BrightBlack, BrightRed, BrightGreen, BrightYellow, BrightBlue, BrightMagenta, BrightCyan, BrightWhite,
     DefaultFg,
     DefaultBg,
+    // 256-color palette index (SGR 38;5;n)
+    Indexed(u8),
+    // Truecolor (SGR 38;2;r;g;b)
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    // Bold text in one of the 8 base colors is drawn in its bright variant
+    pub fn bright(self) -> Color {
+        match self {
+            Color::Black => Color::BrightBlack,
+            Color::Red => Color::BrightRed,
+            Color::Green => Color::BrightGreen,
+            Color::Yellow => Color::BrightYellow,
+            Color::Blue => Color::BrightBlue,
+            Color::Magenta => Color::BrightMagenta,
+            Color::Cyan => Color::BrightCyan,
+            Color::White => Color::BrightWhite,
+            other => other,
+        }
+    }
+
+    // Truecolor has no palette slot
+    pub fn palette_slot(self) -> Option<usize> {
+        let slot = match self {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::BrightBlack => 8,
+            Color::BrightRed => 9,
+            Color::BrightGreen => 10,
+            Color::BrightYellow => 11,
+            Color::BrightBlue => 12,
+            Color::BrightMagenta => 13,
+            Color::BrightCyan => 14,
+            Color::BrightWhite => 15,
+            Color::DefaultFg => FG_SLOT,
+            Color::DefaultBg => BG_SLOT,
+            Color::Indexed(index) => index as usize,
+            Color::Rgb(..) => return None,
+        };
+        Some(slot)
+    }
+}
+
+// Decodes the color after SGR 38/48, either from a single colon-separated
+// group (38:5:n, 38:2:r:g:b, 38:2:cs:r:g:b) or from the semicolon-separated
+// parameters that follow it (38;5;n, 38;2;r;g;b)
+fn extended_color<'a>(group: &[u16], rest: &mut impl Iterator<Item = &'a [u16]>) -> Option<Color> {
+    let byte = |v: u16| v.min(255) as u8;
+    if group.len() > 1 {
+        return match group[1] {
+            5 => group.get(2).map(|&n| Color::Indexed(byte(n))),
+            2 => {
+                // The color space id is optional in the colon form
+                let rgb = if group.len() >= 6 { &group[3..6] } else { group.get(2..5)? };
+                Some(Color::Rgb(byte(rgb[0]), byte(rgb[1]), byte(rgb[2])))
+            }
+            _ => None,
+        };
+    }
+    let mut next = || rest.next().map(|g| g[0]);
+    match next()? {
+        5 => Some(Color::Indexed(byte(next()?))),
+        2 => Some(Color::Rgb(byte(next()?), byte(next()?), byte(next()?))),
+        _ => None,
+    }
+}
+
+// Parses the X11 color specs used by OSC 4/10/11 and the config: "rgb:R/G/B"
+// (1-4 hex digits per channel) or "#RRGGBB" (the '#' is optional)
+pub fn parse_color_spec(spec: &str) -> Option<Rgb> {
+    let spec = spec.trim();
+    if !spec.starts_with("rgb:") {
+        let hex = spec.strip_prefix('#').unwrap_or(spec);
+        if hex.len() != 6 || !hex.is_ascii() { return None; }
+        let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+        let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+        let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+        return Some((r, g, b));
+    }
+
+    let channels = spec.strip_prefix("rgb:")?;
+    let mut parts = channels.split('/');
+    let mut channel = || -> Option<u8> {
+        let part = parts.next()?;
+        if part.is_empty() || part.len() > 4 { return None; }
+        let value = u32::from_str_radix(part, 16).ok()?;
+        let max = (1u32 << (4 * part.len())) - 1;
+        Some((value * 255 / max) as u8)
+    };
+    let rgb = (channel()?, channel()?, channel()?);
+    if parts.next().is_some() { return None; }
+    Some(rgb)
+}
+
+// DECSCUSR cursor styles; the blinking/steady variants map onto the same shape
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+}
+
+// Which mouse events a program asked for (DECSET 1000 / 1002 / 1003)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseMode {
+    Off,
+    // Presses and releases
+    Click,
+    // Also motion while a button is held
+    ButtonEvent,
+    // Also motion with no button held
+    AnyEvent,
+}
+
+impl MouseMode {
+    fn from_decset(mode: u16) -> Option<Self> {
+        match mode {
+            1000 => Some(MouseMode::Click),
+            1002 => Some(MouseMode::ButtonEvent),
+            1003 => Some(MouseMode::AnyEvent),
+            _ => None,
+        }
+    }
+}
+
+// Character sets that can be designated into G0-G3 (ESC ( ) * + followed
+// by the set's final byte)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+    Ascii,
+    // ESC ( 0: lowercase letters become line-drawing characters
+    DecGraphics,
+    // ESC ( A: '#' is the pound sign
+    Uk,
+}
+
+impl Charset {
+    fn from_final(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Charset::Ascii),
+            b'0' => Some(Charset::DecGraphics),
+            b'A' => Some(Charset::Uk),
+            _ => None,
+        }
+    }
+
+    fn translate(self, c: char) -> char {
+        const DEC_GRAPHICS: &[char; 32] = &[
+            '\u{a0}', '◆', '▒', '␉', '␌', '␍', '␊', '°', '±', '␤', '␋', '┘', '┐', '┌', '└', '┼',
+            '⎺', '⎻', '─', '⎼', '⎽', '├', '┤', '┴', '┬', '│', '≤', '≥', 'π', '≠', '£', '·',
+        ];
+        match (self, c) {
+            (Charset::DecGraphics, '_'..='~') => DEC_GRAPHICS[c as usize - '_' as usize],
+            (Charset::Uk, '#') => '£',
+            _ => c,
+        }
+    }
+}
+
+// How mouse reports are written: X10-style bytes, or SGR (DECSET 1006)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseEncoding {
+    Normal,
+    Sgr,
+}
+
+// Requests the terminal can't carry out itself; window.rs owns the
+// clipboard and the window, so it drains and handles these
+#[derive(Clone, Debug, PartialEq)]
+pub enum HostCommand {
+    // OSC 52 write
+    SetClipboard { primary: bool, text: String },
+    // OSC 52 read; the reply echoes the selection parameter
+    QueryClipboard { selection: String, bell_terminated: bool },
+    // CSI 8 ; rows ; cols t
+    ResizeWindow { rows: usize, cols: usize },
+}
+
+// SGR text attributes, kept as bits so Cell stays Copy and small
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Attrs(u16);
+
+impl Attrs {
+    pub const BOLD: Attrs = Attrs(1 << 0);
+    pub const DIM: Attrs = Attrs(1 << 1);
+    pub const ITALIC: Attrs = Attrs(1 << 2);
+    pub const BLINK: Attrs = Attrs(1 << 3);
+    pub const INVERSE: Attrs = Attrs(1 << 4);
+    pub const HIDDEN: Attrs = Attrs(1 << 5);
+    pub const STRIKE: Attrs = Attrs(1 << 6);
+    pub const OVERLINE: Attrs = Attrs(1 << 7);
+
+    pub fn contains(self, other: Attrs) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: Attrs, on: bool) {
+        if on { self.0 |= other.0 } else { self.0 &= !other.0 }
+    }
+}
+
+// SGR codes that switch attributes on or off. 22 clears both bold and dim.
+// Underlines (4, 21, 24) have a style of their own and are handled separately.
+const SGR_ATTRS: &[(u16, Attrs, bool)] = &[
+    (1, Attrs::BOLD, true),
+    (2, Attrs::DIM, true),
+    (3, Attrs::ITALIC, true),
+    (5, Attrs::BLINK, true),
+    (6, Attrs::BLINK, true),
+    (7, Attrs::INVERSE, true),
+    (8, Attrs::HIDDEN, true),
+    (9, Attrs::STRIKE, true),
+    (22, Attrs(Attrs::BOLD.0 | Attrs::DIM.0), false),
+    (23, Attrs::ITALIC, false),
+    (25, Attrs::BLINK, false),
+    (27, Attrs::INVERSE, false),
+    (28, Attrs::HIDDEN, false),
+    (29, Attrs::STRIKE, false),
+    (53, Attrs::OVERLINE, true),
+    (55, Attrs::OVERLINE, false),
+];
+
+// SGR 4 and its 4:n subparameter forms
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnderlineStyle {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct Cell {
     pub char: char,
+    // Zero-width combining characters drawn on top of `char` (accents,
+    // variation selectors); unused slots are '\0'
+    pub marks: [char; 2],
     pub fg: Color,
     pub bg: Color,
-    pub inverse: bool,
+    pub attrs: Attrs,
+    pub underline: UnderlineStyle,
+    // SGR 58; None draws the underline in the foreground color
+    pub underline_color: Option<Color>,
+    // OSC 8 hyperlink, as an index into Terminal::links
+    pub link: Option<u32>,
+}
+
+impl Cell {
+    // The character followed by its combining marks
+    pub fn chars(&self) -> impl Iterator<Item = char> {
+        std::iter::once(self.char).chain(self.marks.into_iter().filter(|&mark| mark != '\0'))
+    }
 }
 
 impl Default for Cell {
     fn default() -> Self {
         Self {
             char: ' ',
+            marks: ['\0'; 2],
             fg: Color::DefaultFg,
             bg: Color::DefaultBg,
-            inverse: false,
+            attrs: Attrs::default(),
+            underline: UnderlineStyle::None,
+            underline_color: None,
+            link: None,
         }
     }
 }
@@ -30,10 +306,20 @@ impl Default for Cell {
 pub struct Terminal {
     pub grid: Vec<Vec<Cell>>,
     pub history: Vec<Vec<Cell>>,
+    // Absolute line number of history[0]. Lines are numbered from the first
+    // one ever written, so this only grows as old history is dropped and a
+    // line number stays valid for as long as the line is kept.
+    pub history_start: usize,
+    // Maximum number of history lines; None keeps everything
+    pub scrollback_lines: Option<usize>,
     pub cols: usize,
     pub rows: usize,
     pub cursor_x: usize,
     pub cursor_y: usize,
+    pub cursor_shape: CursorShape,
+    pub cursor_blink: bool,
+    // The user's configured shape and blink, which DECSCUSR 0 and RIS go back to
+    pub default_cursor: (CursorShape, bool),
     pub scroll_offset: usize,
 
     // Scroll Region Margins (0-indexed, inclusive)
@@ -42,16 +328,237 @@ pub struct Terminal {
 
     pub current_fg: Color,
     pub current_bg: Color,
-    pub current_inverse: bool,
+    pub current_attrs: Attrs,
+    pub current_underline: UnderlineStyle,
+    pub current_underline_color: Option<Color>,
     pub saved_cursor_x: usize,
     pub saved_cursor_y: usize,
-    pub mouse_reporting: bool,
+    pub mouse_mode: MouseMode,
+    pub mouse_encoding: MouseEncoding,
+    pub focus_reporting: bool,
+    // Mode 2004: pastes are wrapped in ESC [ 200 ~ ... ESC [ 201 ~
+    pub bracketed_paste: bool,
+    // Mode 2026: the program is mid-frame, so the window holds off drawing
+    pub sync_update: bool,
+    // DECAWM: wrap to the next line when printing past the last column
+    pub autowrap: bool,
+    // The last column was just written; the next printable char wraps first
+    pub pending_wrap: bool,
+    // DECOM: cursor addressing is relative to, and confined by, the scroll region
+    pub origin_mode: bool,
+    // IRM: printing shifts the rest of the line right instead of overwriting
+    pub insert_mode: bool,
+    // DECSCNM: default foreground and background are swapped screen-wide
+    pub reverse_screen: bool,
+    // DECTCEM: programs hide the cursor while they redraw
+    pub cursor_visible: bool,
+    // XTSAVE (CSI ? Pm s): private mode values for XTRESTORE to put back
+    saved_private_modes: Vec<(u16, bool)>,
+    // G0-G3, which of them SI/SO (or LS2/LS3) shifted in, and the one SS2/SS3
+    // picked for the next character only
+    pub charsets: [Charset; 4],
+    pub active_charset: usize,
+    single_shift: Option<usize>,
+    // LNM: LF also returns to column 0, and Enter sends CR LF
+    pub newline_mode: bool,
+    // DECCKM: arrow keys send SS3 (ESC O A) instead of CSI (ESC [ A)
+    pub app_cursor_keys: bool,
+
+    // Tab stops, one flag per column; every 8th column by default
+    pub tab_stops: Vec<bool>,
+
+    // Primary screen contents, parked here while the alternate screen is active
+    pub saved_grid: Option<Vec<Vec<Cell>>>,
 
     pub title: String,
+    // Titles saved by CSI 22 t, restored by CSI 23 t
+    pub title_stack: Vec<String>,
+    // Text area size in pixels, kept up to date by the window for CSI 14 t
+    pub pixel_size: (u32, u32),
+
+    // Colors from the theme, used for OSC color queries and as the fallback
+    // for slots that have not been overridden
+    pub base_palette: Vec<Rgb>,
+    // Runtime palette changes from OSC 4/10/11
+    pub palette_overrides: Vec<Option<Rgb>>,
+
+    // Bytes the terminal wants to send back to the application (query
+    // replies). The parser never writes to the pty itself; the window drains
+    // this after each batch of output.
+    pub pending_output: Vec<u8>,
+    // Actions for the window to perform (clipboard access, resizing)
+    pub pending_commands: Vec<HostCommand>,
+
+    // OSC 8 hyperlink targets referenced by Cell::link, and the one being printed
+    pub links: Vec<String>,
+    pub active_link: Option<u32>,
+    // Each target's index in `links`, so a URI is stored once
+    link_ids: HashMap<String, u32>,
+    // Unused targets are dropped once `links` grows to this many
+    link_compact_at: usize,
+    // Link under the mouse pointer, underlined by the renderer
+    pub hovered_link: Option<u32>,
+
+    // Set by BEL; the window consumes it and flashes/beeps
+    pub bell_pending: bool,
+
+    // Last printed character, replayed by REP (CSI b)
+    pub last_char: Option<char>,
+
+    // Printing over a cell just backspaced onto merges the two the way a
+    // typewriter would: same char is bold, underscore underlines. Only a
+    // char, one BS and the next char count, so line editing isn't caught.
+    pub overstrike: bool,
+    // The cell the last char was printed into, until anything else arrives
+    last_printed: Option<(usize, usize)>,
+    // That cell, once a single BS has moved back onto it
+    overstrike_cell: Option<(usize, usize)>,
+
+    // Scrollback search results as (absolute line, column range), oldest
+    // first, and the index of the match the view is centered on
+    pub search_matches: Vec<(usize, Range<usize>)>,
+    pub search_current: Option<usize>,
+
+    // Absolute lines where the shell marked a prompt start (OSC 133;A),
+    // oldest first
+    pub prompt_lines: Vec<usize>,
+
+    // Escape sequence state carried between feed() calls, so a sequence
+    // split across two pty reads is still recognised
+    parser: Parser,
 
     // Selection Tracking
     pub selection_start: Option<(usize, usize)>,
     pub selection_end: Option<(usize, usize)>,
+    // Block selection: the same column range on every line instead of
+    // flowing from start to end
+    pub selection_rect: bool,
+}
+
+// The crate version as a single number, 0.1.0 -> 100, for secondary DA
+fn version_code() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .fold(0, |code, part| code * 100 + part)
+}
+
+// xterm's 16 ANSI colors, which a Terminal starts with until a theme is set
+pub const XTERM_ANSI: [Rgb; 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+// The full palette for the given ANSI colors: those 16, the xterm 6x6x6
+// cube and grayscale ramp, then default fg/bg
+pub fn xterm_palette(ansi: &[Rgb; 16], foreground: Rgb, background: Rgb) -> Vec<Rgb> {
+    let mut palette = vec![(0, 0, 0); PALETTE_SLOTS];
+    palette[..16].copy_from_slice(ansi);
+    let level = |v: usize| if v == 0 { 0 } else { (55 + v * 40) as u8 };
+    for i in 0..216 {
+        palette[16 + i] = (level(i / 36), level((i / 6) % 6), level(i % 6));
+    }
+    for i in 0..24 {
+        let gray = (8 + i * 10) as u8;
+        palette[232 + i] = (gray, gray, gray);
+    }
+    palette[FG_SLOT] = foreground;
+    palette[BG_SLOT] = background;
+    palette
+}
+
+fn default_tab_stops(cols: usize) -> Vec<bool> {
+    (0..cols).map(|col| col % 8 == 0).collect()
+}
+
+// East Asian wide characters and most emoji take two cells. The second
+// cell holds '\0' and is skipped when drawing and copying.
+pub fn is_wide(c: char) -> bool {
+    c.width() == Some(2)
+}
+
+// A row as a string, without the blank cells at the end
+fn row_text(row: &[Cell]) -> String {
+    let line: String = row.iter()
+        .filter(|cell| cell.char != '\0')
+        .flat_map(Cell::chars)
+        .collect();
+    line.trim_end().to_string()
+}
+
+// SGR parameters for a color, as foreground (base 30) or background (base 40)
+fn color_sgr(color: Color, base: u16) -> String {
+    match color {
+        Color::DefaultFg | Color::DefaultBg => (base + 9).to_string(),
+        Color::Indexed(index) => format!("{};5;{}", base + 8, index),
+        Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        // palette_slot() is 0-15 for the named colors
+        named => {
+            let slot = named.palette_slot().unwrap_or(0) as u16;
+            if slot < 8 { (base + slot).to_string() } else { (base + 60 + slot - 8).to_string() }
+        }
+    }
+}
+
+// The SGR sequence that reproduces a cell's style from a reset state
+fn cell_sgr(cell: &Cell) -> String {
+    let mut params = vec!["0".to_string()];
+    for &(code, attr, on) in SGR_ATTRS {
+        // 6 (rapid blink) is an alias for 5
+        if on && code != 6 && cell.attrs.contains(attr) { params.push(code.to_string()); }
+    }
+    match cell.underline {
+        UnderlineStyle::None => {}
+        UnderlineStyle::Single => params.push("4".to_string()),
+        UnderlineStyle::Double => params.push("4:2".to_string()),
+        UnderlineStyle::Curly => params.push("4:3".to_string()),
+        UnderlineStyle::Dotted => params.push("4:4".to_string()),
+        UnderlineStyle::Dashed => params.push("4:5".to_string()),
+    }
+    if cell.fg != Color::DefaultFg { params.push(color_sgr(cell.fg, 30)); }
+    if cell.bg != Color::DefaultBg { params.push(color_sgr(cell.bg, 40)); }
+    if let Some(color) = cell.underline_color {
+        params.push(color_sgr(color, 50));
+    }
+    format!("\x1b[{}m", params.join(";"))
+}
+
+fn same_style(a: &Cell, b: &Cell) -> bool {
+    a.fg == b.fg
+        && a.bg == b.bg
+        && a.attrs == b.attrs
+        && a.underline == b.underline
+        && a.underline_color == b.underline_color
+}
+
+// Like row_text(), but with an SGR sequence wherever the style changes and a
+// reset at the end of the line
+fn row_ansi_text(row: &[Cell]) -> String {
+    // Keep trailing blanks only if they'd show, e.g. a colored background
+    let plain = |cell: &Cell| {
+        matches!(cell.char, ' ' | '\0')
+            && cell.bg == Color::DefaultBg
+            && !cell.attrs.contains(Attrs::INVERSE)
+            && cell.underline == UnderlineStyle::None
+    };
+    let len = row.iter().rposition(|cell| !plain(cell)).map_or(0, |i| i + 1);
+
+    let mut text = String::new();
+    let mut style = Cell::default();
+    for cell in row[..len].iter().filter(|cell| cell.char != '\0') {
+        if !same_style(cell, &style) {
+            text.push_str(&cell_sgr(cell));
+            style = *cell;
+        }
+        text.extend(cell.chars());
+    }
+    if !same_style(&style, &Cell::default()) {
+        text.push_str("\x1b[0m");
+    }
+    text
 }
 
 impl Terminal {
@@ -60,10 +567,15 @@ impl Terminal {
         Self {
             grid,
             history: Vec::new(),
+            history_start: 0,
+            scrollback_lines: Some(10_000),
             cols,
             rows,
             cursor_x: 0,
             cursor_y: 0,
+            cursor_shape: CursorShape::Block,
+            cursor_blink: true,
+            default_cursor: (CursorShape::Block, true),
             scroll_offset: 0,
 
             // Default scroll region is the full screen
@@ -72,26 +584,108 @@ impl Terminal {
 
             current_fg: Color::DefaultFg,
             current_bg: Color::DefaultBg,
-            current_inverse: false,
+            current_attrs: Attrs::default(),
+            current_underline: UnderlineStyle::None,
+            current_underline_color: None,
             saved_cursor_x: 0,
             saved_cursor_y: 0,
-            mouse_reporting: false,
+            mouse_mode: MouseMode::Off,
+            mouse_encoding: MouseEncoding::Normal,
+            focus_reporting: false,
+            bracketed_paste: false,
+            sync_update: false,
+            app_cursor_keys: false,
+            newline_mode: false,
+            reverse_screen: false,
+            insert_mode: false,
+            origin_mode: false,
+            autowrap: true,
+            pending_wrap: false,
+            cursor_visible: true,
+            saved_private_modes: Vec::new(),
+            charsets: [Charset::Ascii; 4],
+            active_charset: 0,
+            single_shift: None,
+
+            tab_stops: default_tab_stops(cols),
+
+            saved_grid: None,
+
             title: "RoseTerm".to_string(),
+            title_stack: Vec::new(),
+            pixel_size: (0, 0),
+
+            base_palette: xterm_palette(&XTERM_ANSI, XTERM_ANSI[7], XTERM_ANSI[0]),
+            palette_overrides: vec![None; PALETTE_SLOTS],
+
+            pending_output: Vec::new(),
+            pending_commands: Vec::new(),
+
+            links: Vec::new(),
+            active_link: None,
+            link_ids: HashMap::new(),
+            link_compact_at: LINK_COMPACT_MIN,
+            hovered_link: None,
+
+            bell_pending: false,
+
+            last_char: None,
+            overstrike: false,
+            last_printed: None,
+            overstrike_cell: None,
+
+            search_matches: Vec::new(),
+            search_current: None,
+            prompt_lines: Vec::new(),
+
+            parser: Parser::new(),
 
             selection_start: None,
             selection_end: None,
+            selection_rect: false,
         }
     }
 
-    pub fn start_selection(&mut self, col: usize, row: usize) {
-        self.selection_start = Some((col, row));
-        self.selection_end = Some((col, row));
+    // Runs raw pty output through the escape sequence parser
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut parser = std::mem::take(&mut self.parser);
+        for &byte in bytes {
+            parser.advance(self, byte);
+        }
+        self.parser = parser;
+    }
+
+    // Selections are kept as (col, absolute line) so they stay on the same
+    // text while the view scrolls; callers pass screen rows
+    pub fn start_selection(&mut self, col: usize, row: usize, rect: bool) {
+        let line = self.visible_line(row);
+        self.selection_rect = rect;
+        self.selection_start = Some((col, line));
+        self.selection_end = Some((col, line));
     }
 
     pub fn update_selection(&mut self, col: usize, row: usize) {
         if self.selection_start.is_some() {
-            self.selection_end = Some((col, row));
+            self.selection_end = Some((col, self.visible_line(row)));
+        }
+    }
+
+    // Selects the word under a screen cell: the run of cells around it that
+    // are neither whitespace nor in `separators`. On a separator, just that cell.
+    pub fn select_word(&mut self, col: usize, row: usize, separators: &str) {
+        let line = self.visible_line(row);
+        let Some(cells) = self.line_at_absolute(line) else { return };
+        let in_word = |cell: &Cell| !cell.char.is_whitespace() && !separators.contains(cell.char);
+        let col = col.min(cells.len().saturating_sub(1));
+
+        let (mut start, mut end) = (col, col);
+        if cells.get(col).is_some_and(in_word) {
+            while start > 0 && in_word(&cells[start - 1]) { start -= 1; }
+            while end + 1 < cells.len() && in_word(&cells[end + 1]) { end += 1; }
         }
+        self.selection_rect = false;
+        self.selection_start = Some((start, line));
+        self.selection_end = Some((end, line));
     }
 
     pub fn clear_selection(&mut self) {
@@ -99,45 +693,175 @@ impl Terminal {
         self.selection_end = None;
     }
 
-    pub fn is_selected(&self, col: usize, row: usize) -> bool {
-        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-            let (p1, p2) = if start.1 < end.1 || (start.1 == end.1 && start.0 <= end.0) {
-                (start, end)
+    // Selection endpoints in reading order
+    fn selection_bounds(&self) -> Option<((usize, usize), (usize, usize))> {
+        let (start, end) = (self.selection_start?, self.selection_end?);
+        if start.1 < end.1 || (start.1 == end.1 && start.0 <= end.0) {
+            Some((start, end))
+        } else {
+            Some((end, start))
+        }
+    }
+
+    // Whether a cell of an absolute line (see visible_line()) is selected
+    pub fn is_selected(&self, col: usize, line: usize) -> bool {
+        let Some((p1, p2)) = self.selection_bounds() else { return false };
+
+        if line < p1.1 || line > p2.1 { return false; }
+        if self.selection_rect {
+            return col >= p1.0.min(p2.0) && col <= p1.0.max(p2.0);
+        }
+        if line == p1.1 && line == p2.1 { return col >= p1.0 && col <= p2.0; }
+        if line == p1.1 { return col >= p1.0; }
+        if line == p2.1 { return col <= p2.0; }
+        true
+    }
+
+    // The selected part of each line, in reading order
+    fn selected_rows(&self) -> Vec<&[Cell]> {
+        let mut rows = Vec::new();
+        let Some((p1, p2)) = self.selection_bounds() else { return rows };
+
+        for line in p1.1..=p2.1 {
+            let Some(row_data) = self.line_at_absolute(line) else { break };
+            let (start_col, end_col) = if self.selection_rect {
+                (p1.0.min(p2.0), p1.0.max(p2.0))
             } else {
-                (end, start)
+                (if line == p1.1 { p1.0 } else { 0 }, if line == p2.1 { p2.0 } else { self.cols - 1 })
             };
 
-            if row < p1.1 || row > p2.1 { return false; }
-            if row == p1.1 && row == p2.1 { return col >= p1.0 && col <= p2.0; }
-            if row == p1.1 { return col >= p1.0; }
-            if row == p2.1 { return col <= p2.0; }
-            return true;
+            let end_col = end_col.min(row_data.len().saturating_sub(1));
+            rows.push(if start_col <= end_col { &row_data[start_col..=end_col] } else { &[][..] });
         }
-        false
+        rows
     }
 
     pub fn get_selected_text(&self) -> String {
-        let mut text = String::new();
-        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-            let (p1, p2) = if start.1 < end.1 || (start.1 == end.1 && start.0 <= end.0) {
-                (start, end)
-            } else {
-                (end, start)
-            };
+        let lines: Vec<String> = self.selected_rows().into_iter().map(row_text).collect();
+        lines.join("\n")
+    }
 
-            for r in p1.1..=p2.1 {
-                let row_data = self.get_visible_row(r);
-                let start_col = if r == p1.1 { p1.0 } else { 0 };
-                let end_col = if r == p2.1 { p2.0 } else { self.cols - 1 };
+    // The selection with SGR sequences around each run of identically
+    // styled cells, so the colors survive a paste into a file
+    pub fn get_selected_ansi_text(&self) -> String {
+        let lines: Vec<String> = self.selected_rows().into_iter().map(row_ansi_text).collect();
+        lines.join("\n")
+    }
 
-                for c in start_col..=end_col {
-                    if c < row_data.len() {
-                        text.push(row_data[c].char);
-                    }
+    // Number of lines kept, history and screen together. The oldest is
+    // absolute line history_start.
+    pub fn total_lines(&self) -> usize {
+        self.history.len() + self.grid.len()
+    }
+
+    // Absolute line number of the top screen row
+    pub fn screen_start(&self) -> usize {
+        self.history_start + self.history.len()
+    }
+
+    // A row by absolute line number: history first, then the screen. None
+    // once the line has been trimmed away.
+    pub fn line_at_absolute(&self, line: usize) -> Option<&Vec<Cell>> {
+        let index = line.checked_sub(self.history_start)?;
+        match index.checked_sub(self.history.len()) {
+            Some(screen_row) => self.grid.get(screen_row),
+            None => self.history.get(index),
+        }
+    }
+
+    // Case-insensitive search over history and screen, by absolute line
+    pub fn search(&self, query: &str) -> Vec<(usize, Range<usize>)> {
+        let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+        let needle: Vec<char> = query.chars().map(fold).collect();
+        let mut matches = Vec::new();
+        if needle.is_empty() { return matches; }
+
+        for (index, row) in self.history.iter().chain(self.grid.iter()).enumerate() {
+            let line = self.history_start + index;
+            // A wide char is one char of the haystack; `cols` maps each back
+            // to its first cell, so a match still covers both halves
+            let (cols, hay): (Vec<usize>, Vec<char>) = row.iter()
+                .enumerate()
+                .filter(|(_, cell)| cell.char != '\0')
+                .map(|(col, cell)| (col, fold(cell.char)))
+                .unzip();
+            let mut i = 0;
+            while i + needle.len() <= hay.len() {
+                if hay[i..i + needle.len()] == needle[..] {
+                    let end = cols.get(i + needle.len()).copied().unwrap_or(row.len());
+                    matches.push((line, cols[i]..end));
+                    i += needle.len();
+                } else {
+                    i += 1;
                 }
-                if r != p2.1 { text.push('\n'); }
             }
         }
+        matches
+    }
+
+    // Absolute line number (as used by search and selection) of a row on screen
+    pub fn visible_line(&self, screen_y: usize) -> usize {
+        let offset = self.scroll_offset.min(self.history.len());
+        self.screen_start() - offset + screen_y.min(self.rows.saturating_sub(1))
+    }
+
+    // Scrolls so an absolute line sits in the middle of the screen, as far
+    // as the scrollback allows
+    pub fn center_line(&mut self, line: usize) {
+        self.set_scroll_offset((self.screen_start() + self.rows / 2).saturating_sub(line));
+    }
+
+    // Some(true) for the current match, Some(false) for any other match
+    pub fn search_highlight(&self, col: usize, line: usize) -> Option<bool> {
+        if self.search_matches.is_empty() { return None; }
+        // Matches are sorted by line, so only look at the ones on this line
+        let first = self.search_matches.partition_point(|(l, _)| *l < line);
+        self.search_matches[first..].iter()
+            .enumerate()
+            .take_while(|(_, (l, _))| *l == line)
+            .find(|(_, (_, cols))| cols.contains(&col))
+            .map(|(i, _)| Some(first + i) == self.search_current)
+    }
+
+    // Scrolls so the previous (older) or next prompt mark is the top line.
+    // Past the newest mark the view returns to the bottom.
+    pub fn jump_to_prompt(&mut self, older: bool) {
+        let top = self.visible_line(0);
+        let target = if older {
+            self.prompt_lines.iter().rev().find(|&&line| line < top)
+        } else {
+            self.prompt_lines.iter().find(|&&line| line > top)
+        };
+        match target {
+            Some(&line) => self.set_scroll_offset(self.screen_start().saturating_sub(line)),
+            None if !older => self.scroll_to_bottom(),
+            None => false,
+        };
+    }
+
+    pub fn clear_search(&mut self) {
+        self.search_matches.clear();
+        self.search_current = None;
+    }
+
+    // The rows currently in view as plain text, one line per row with
+    // trailing blanks trimmed. Wide characters appear once, with their marks.
+    pub fn screen_to_string(&self) -> String {
+        (0..self.rows).map(|row| row_text(self.get_visible_row(row)) + "\n").collect()
+    }
+
+    // Scrollback followed by the screen, like screen_to_string(). Empty rows
+    // below the last output are dropped.
+    pub fn scrollback_to_string(&self) -> String {
+        let mut lines: Vec<String> = self.history.iter()
+            .chain(self.grid.iter())
+            .map(|row| row_text(row))
+            .collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        let mut text = lines.join("\n");
+        text.push('\n');
         text
     }
 
@@ -149,11 +873,9 @@ impl Terminal {
             let removed = self.grid.remove(self.scroll_top);
 
             // Only push to history if we are scrolling from the absolute top (0)
-            if self.scroll_top == 0 {
-                if self.history.len() > 10_000 {
-                    self.history.remove(0);
-                }
-                self.history.push(removed);
+            // of the primary screen; the alternate screen has no scrollback
+            if self.scroll_top == 0 && !self.is_alt_screen() {
+                self.push_history(removed);
             }
 
             // Insert a new blank line at the bottom of the region
@@ -168,39 +890,115 @@ impl Terminal {
         }
     }
 
-    pub fn scroll_up(&mut self, lines: usize) {
-        self.scroll_offset = (self.scroll_offset + lines).min(self.history.len());
+    fn push_history(&mut self, row: Vec<Cell>) {
+        if self.scrollback_lines == Some(0) { return; }
+        self.history.push(row);
+        self.trim_history();
+    }
+
+    // Changes the scrollback limit, dropping the oldest lines if it shrank
+    pub fn set_scrollback_lines(&mut self, limit: Option<usize>) {
+        self.scrollback_lines = limit;
+        self.trim_history();
+    }
+
+    fn trim_history(&mut self) {
+        if let Some(limit) = self.scrollback_lines {
+            if self.history.len() > limit {
+                self.drop_history(self.history.len() - limit);
+            }
+        }
+        self.scroll_offset = self.scroll_offset.min(self.history.len());
+    }
+
+    // Drops the oldest `lines` of history. Other lines keep their numbers;
+    // marks, matches and a selection touching the dropped ones go with them.
+    fn drop_history(&mut self, lines: usize) {
+        self.history.drain(..lines);
+        self.history_start += lines;
+        let start = self.history_start;
+        self.prompt_lines.retain(|&line| line >= start);
+        if self.search_matches.first().is_some_and(|(line, _)| *line < start) {
+            self.clear_search();
+        }
+        let dropped = |point: Option<(usize, usize)>| point.is_some_and(|(_, line)| line < start);
+        if dropped(self.selection_start) || dropped(self.selection_end) {
+            self.clear_selection();
+        }
+        if self.links.len() >= self.link_compact_at {
+            self.compact_links();
+        }
+    }
+
+    // Moves the view to `offset` lines back from the bottom, clamped to the
+    // history there is now. These all return whether the view moved, so
+    // callers only redraw when it did.
+    pub fn set_scroll_offset(&mut self, offset: usize) -> bool {
+        let offset = offset.min(self.history.len());
+        let moved = offset != self.scroll_offset;
+        self.scroll_offset = offset;
+        moved
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) -> bool {
+        self.set_scroll_offset(self.scroll_offset.saturating_add(lines))
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) -> bool {
+        self.set_scroll_offset(self.scroll_offset.min(self.history.len()).saturating_sub(lines))
     }
 
-    pub fn scroll_down(&mut self, lines: usize) {
-        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+    // Back to the live screen, e.g. when the user types
+    pub fn scroll_to_bottom(&mut self) -> bool {
+        self.set_scroll_offset(0)
     }
 
+    // Row shown at screen_y with the current scroll_offset. Offsets past the
+    // oldest history line are treated as the oldest line.
     pub fn get_visible_row(&self, screen_y: usize) -> &Vec<Cell> {
-        if self.scroll_offset == 0 {
-            &self.grid[screen_y]
+        let screen_y = screen_y.min(self.rows - 1);
+        self.line_at_absolute(self.visible_line(screen_y)).unwrap_or(&self.grid[screen_y])
+    }
+
+    // Attaches a zero-width character to the one printed last, without
+    // moving the cursor. Marks past the second, or with nothing before them
+    // on the line, are dropped.
+    fn add_mark(&mut self, mark: char) {
+        let col = if self.pending_wrap {
+            self.char_start(self.cursor_y, self.cursor_x)
+        } else if self.cursor_x > 0 {
+            self.char_start(self.cursor_y, self.cursor_x - 1)
         } else {
-            let total_history = self.history.len();
-            let rows_from_bottom = self.rows - 1 - screen_y;
-            let effective_offset = self.scroll_offset + rows_from_bottom;
+            return;
+        };
+        let cell = &mut self.grid[self.cursor_y][col];
+        if let Some(slot) = cell.marks.iter_mut().find(|slot| **slot == '\0') {
+            *slot = mark;
+        }
+    }
 
-            if effective_offset >= self.rows {
-                let history_index = total_history - (effective_offset - self.rows + 1);
-                &self.history[history_index]
-            } else {
-                let grid_index = self.rows - effective_offset - 1;
-                &self.grid[grid_index]
-            }
+    // Column where the character covering a cell starts: one to the left
+    // for the second half of a wide character
+    fn char_start(&self, row: usize, col: usize) -> usize {
+        if col > 0 && self.grid[row].get(col).is_some_and(|cell| cell.char == '\0') { col - 1 } else { col }
+    }
+
+    // Before a cell is overwritten: blanks the other half of a wide
+    // character covering it, so no half is left behind on its own
+    fn split_wide(&mut self, row: usize, col: usize) {
+        let line = &mut self.grid[row];
+        if col > 0 && line.get(col).is_some_and(|cell| cell.char == '\0') {
+            line[col - 1].char = ' ';
+        }
+        if line.get(col + 1).is_some_and(|cell| cell.char == '\0') {
+            line[col + 1].char = ' ';
         }
     }
 
+    // What erased and inserted cells become: blank, in the current
+    // background (BCE) but none of the other attributes
     fn blank_cell(&self) -> Cell {
-        Cell {
-            char: ' ',
-            fg: self.current_fg,
-            bg: self.current_bg,
-            inverse: self.current_inverse,
-        }
+        Cell { bg: self.current_bg, ..Cell::default() }
     }
 
     pub fn resize(&mut self, new_cols: usize, new_rows: usize) {
@@ -208,6 +1006,18 @@ impl Terminal {
         for row in &mut self.grid {
             row.resize(new_cols, Cell::default());
         }
+        if let Some(saved) = &mut self.saved_grid {
+            saved.resize(new_rows, vec![Cell::default(); new_cols]);
+            for row in saved {
+                row.resize(new_cols, Cell::default());
+            }
+        }
+        // New columns get the default stops; existing ones keep any HTS changes
+        let old_cols = self.tab_stops.len();
+        self.tab_stops.resize(new_cols, false);
+        for col in old_cols..new_cols {
+            self.tab_stops[col] = col % 8 == 0;
+        }
         self.rows = new_rows;
         self.cols = new_cols;
         // Reset scroll region to full screen on resize
@@ -216,76 +1026,560 @@ impl Terminal {
 
         self.cursor_x = self.cursor_x.min(self.cols - 1);
         self.cursor_y = self.cursor_y.min(self.rows - 1);
+        self.pending_wrap = false;
+        self.scroll_offset = 0;
+    }
+
+    pub fn link_at(&self, col: usize, row: usize) -> Option<u32> {
+        if row >= self.rows { return None; }
+        self.get_visible_row(row).get(col).and_then(|cell| cell.link)
+    }
+
+    // OSC 8: an empty URI closes the current link
+    fn set_active_link(&mut self, uri: String) {
+        if uri.is_empty() {
+            self.active_link = None;
+            return;
+        }
+        let id = match self.link_ids.get(&uri) {
+            Some(&id) => id,
+            None => {
+                let id = self.links.len() as u32;
+                self.links.push(uri.clone());
+                self.link_ids.insert(uri, id);
+                id
+            }
+        };
+        self.active_link = Some(id);
+    }
+
+    // Forgets the link targets no cell refers to any more and renumbers the
+    // rest. Run as history is dropped, once the list has doubled since last time.
+    fn compact_links(&mut self) {
+        let mut old_links = std::mem::take(&mut self.links);
+        let mut remap: Vec<Option<u32>> = vec![None; old_links.len()];
+        let mut links = Vec::new();
+        let mut keep = |link: &mut Option<u32>| {
+            if let Some(old) = *link {
+                *link = Some(*remap[old as usize].get_or_insert_with(|| {
+                    links.push(std::mem::take(&mut old_links[old as usize]));
+                    links.len() as u32 - 1
+                }));
+            }
+        };
+        let rows = self.history.iter_mut().chain(&mut self.grid).chain(self.saved_grid.iter_mut().flatten());
+        for cell in rows.flatten() {
+            keep(&mut cell.link);
+        }
+        keep(&mut self.active_link);
+        keep(&mut self.hovered_link);
+
+        self.links = links;
+        self.link_ids = self.links.iter().enumerate().map(|(id, uri)| (uri.clone(), id as u32)).collect();
+        self.link_compact_at = (self.links.len() * 2).max(LINK_COMPACT_MIN);
+    }
+
+    pub fn palette_color(&self, slot: usize) -> Rgb {
+        self.palette_overrides[slot].unwrap_or(self.base_palette[slot])
+    }
+
+    // The RGB value a cell color currently maps to
+    pub fn resolve_color(&self, color: Color) -> Rgb {
+        match (color, color.palette_slot()) {
+            (Color::Rgb(r, g, b), _) => (r, g, b),
+            (_, Some(slot)) => self.palette_color(slot),
+            (_, None) => self.palette_color(FG_SLOT),
+        }
+    }
+
+    // Queues a reply to a query for the window to send to the application
+    pub fn respond(&mut self, reply: &str) {
+        self.pending_output.extend_from_slice(reply.as_bytes());
+    }
+
+    // Applies an OSC color spec to a palette slot, or answers it if it is a query
+    fn osc_color(&mut self, slot: usize, prefix: &str, spec: &[u8], bell_terminated: bool) {
+        if spec == b"?" {
+            let (r, g, b) = self.palette_color(slot);
+            let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+            let reply = format!(
+                "\x1b]{};rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}{}",
+                prefix, r, r, g, g, b, b, terminator
+            );
+            self.respond(&reply);
+        } else if let Some(rgb) = std::str::from_utf8(spec).ok().and_then(parse_color_spec) {
+            self.palette_overrides[slot] = Some(rgb);
+        }
+    }
+
+    pub fn is_alt_screen(&self) -> bool {
+        self.saved_grid.is_some()
+    }
+
+    // SCOSC / SCORC and the cursor half of 1048 / 1049
+    fn save_cursor(&mut self) {
+        self.saved_cursor_x = self.cursor_x;
+        self.saved_cursor_y = self.cursor_y;
+    }
+
+    fn restore_cursor(&mut self) {
+        self.cursor_x = self.saved_cursor_x.min(self.cols - 1);
+        self.cursor_y = self.saved_cursor_y.min(self.rows - 1);
+    }
+
+    pub fn enter_alt_screen(&mut self, save_cursor: bool) {
+        if self.is_alt_screen() { return; }
+        if save_cursor { self.save_cursor(); }
+        let blank = vec![vec![Cell::default(); self.cols]; self.rows];
+        self.saved_grid = Some(std::mem::replace(&mut self.grid, blank));
         self.scroll_offset = 0;
+        self.reset_margins();
+        self.clear_selection();
+    }
+
+    pub fn exit_alt_screen(&mut self, restore_cursor: bool) {
+        if let Some(primary) = self.saved_grid.take() {
+            self.grid = primary;
+            if restore_cursor { self.restore_cursor(); }
+            // A region the full-screen app left behind is meaningless to the shell
+            self.reset_margins();
+            self.clear_selection();
+        }
+    }
+
+    // HT / CHT: forward over `count` tab stops, stopping at the last column
+    fn tab_forward(&mut self, count: usize) {
+        for _ in 0..count {
+            match (self.cursor_x + 1..self.cols).find(|&col| self.tab_stops[col]) {
+                Some(col) => self.cursor_x = col,
+                None => {
+                    self.cursor_x = self.cols - 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    // CBT: back over `count` tab stops, stopping at the first column
+    fn tab_backward(&mut self, count: usize) {
+        for _ in 0..count {
+            match (0..self.cursor_x).rev().find(|&col| self.tab_stops[col]) {
+                Some(col) => self.cursor_x = col,
+                None => {
+                    self.cursor_x = 0;
+                    break;
+                }
+            }
+        }
+    }
+
+    // Scroll region back to the whole screen
+    fn reset_margins(&mut self) {
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows.saturating_sub(1);
+    }
+
+    // Maps a 0-based row from CUP/VPA to a screen row, honoring DECOM
+    fn absolute_row(&self, row: usize) -> usize {
+        if self.origin_mode {
+            (self.scroll_top + row).min(self.scroll_bottom)
+        } else {
+            row.min(self.rows - 1)
+        }
+    }
+
+    // Top-left of the screen, or of the scroll region under DECOM
+    fn home_cursor(&mut self) {
+        self.cursor_x = 0;
+        self.cursor_y = if self.origin_mode { self.scroll_top } else { 0 };
+    }
+
+    // DECSET / DECRST (CSI ? Pm h / l)
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            1 => self.app_cursor_keys = enable,
+            5 => self.reverse_screen = enable,
+            6 => {
+                self.origin_mode = enable;
+                self.home_cursor();
+            }
+            7 => {
+                self.autowrap = enable;
+                if !enable { self.pending_wrap = false; }
+            }
+            // Turning off a mode other than the active one changes nothing
+            1000 | 1002 | 1003 => {
+                let requested = MouseMode::from_decset(mode);
+                if enable {
+                    self.mouse_mode = requested.unwrap_or(MouseMode::Off);
+                } else if requested == Some(self.mouse_mode) {
+                    self.mouse_mode = MouseMode::Off;
+                }
+            }
+            1006 => self.mouse_encoding = if enable { MouseEncoding::Sgr } else { MouseEncoding::Normal },
+            1004 => self.focus_reporting = enable,
+            2004 => self.bracketed_paste = enable,
+            2026 => self.sync_update = enable,
+            47 | 1047 => if enable { self.enter_alt_screen(false) } else { self.exit_alt_screen(false) },
+            1048 => if enable { self.save_cursor() } else { self.restore_cursor() },
+            1049 => if enable { self.enter_alt_screen(true) } else { self.exit_alt_screen(true) },
+            25 => self.cursor_visible = enable,
+            _ => {}
+        }
+    }
+
+    // The current value of a private mode XTSAVE can save. Screen switches
+    // are left out: restoring one would swap the screen under the program.
+    fn private_mode(&self, mode: u16) -> Option<bool> {
+        Some(match mode {
+            1 => self.app_cursor_keys,
+            5 => self.reverse_screen,
+            6 => self.origin_mode,
+            7 => self.autowrap,
+            25 => self.cursor_visible,
+            1000 | 1002 | 1003 => MouseMode::from_decset(mode) == Some(self.mouse_mode),
+            1006 => self.mouse_encoding == MouseEncoding::Sgr,
+            1004 => self.focus_reporting,
+            2004 => self.bracketed_paste,
+            2026 => self.sync_update,
+            _ => return None,
+        })
+    }
+
+    fn save_private_mode(&mut self, mode: u16) {
+        let Some(value) = self.private_mode(mode) else { return };
+        self.saved_private_modes.retain(|&(m, _)| m != mode);
+        self.saved_private_modes.push((mode, value));
+    }
+
+    fn restore_private_mode(&mut self, mode: u16) {
+        let Some(&(_, value)) = self.saved_private_modes.iter().find(|&&(m, _)| m == mode) else { return };
+        match mode {
+            // Unlike DECSET, putting DECOM back doesn't home the cursor
+            6 => self.origin_mode = value,
+            _ => self.set_private_mode(mode, value),
+        }
+    }
+
+    // SM / RM (CSI Pm h / l)
+    fn set_ansi_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            4 => self.insert_mode = enable,
+            20 => self.newline_mode = enable,
+            _ => {}
+        }
+    }
+
+    // Sets the configured cursor and switches to it
+    pub fn set_default_cursor(&mut self, shape: CursorShape, blink: bool) {
+        self.default_cursor = (shape, blink);
+        (self.cursor_shape, self.cursor_blink) = self.default_cursor;
+    }
+
+    // SGR 0: default colors, no attributes
+    fn reset_sgr(&mut self) {
+        self.current_fg = Color::DefaultFg;
+        self.current_bg = Color::DefaultBg;
+        self.current_attrs = Attrs::default();
+        self.current_underline = UnderlineStyle::None;
+        self.current_underline_color = None;
+    }
+
+    // DECSTR: reset modes, margins and SGR but leave the screen alone
+    pub fn soft_reset(&mut self) {
+        self.reset_sgr();
+        self.reset_margins();
+        self.app_cursor_keys = false;
+        self.autowrap = true;
+        self.saved_cursor_x = 0;
+        self.saved_cursor_y = 0;
+        self.insert_mode = false;
+        self.origin_mode = false;
+        self.cursor_visible = true;
+        self.pending_wrap = false;
+        self.charsets = [Charset::Ascii; 4];
+        self.active_charset = 0;
+        self.single_shift = None;
+    }
+
+    // RIS: back to the state of a freshly spawned terminal
+    pub fn full_reset(&mut self) {
+        self.soft_reset();
+        (self.cursor_shape, self.cursor_blink) = self.default_cursor;
+        self.grid = vec![vec![Cell::default(); self.cols]; self.rows];
+        self.drop_history(self.history.len());
+        self.prompt_lines.clear();
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.scroll_offset = 0;
+        self.mouse_mode = MouseMode::Off;
+        self.mouse_encoding = MouseEncoding::Normal;
+        self.focus_reporting = false;
+        self.bracketed_paste = false;
+        self.sync_update = false;
+        self.newline_mode = false;
+        self.reverse_screen = false;
+        self.saved_private_modes.clear();
+        self.tab_stops = default_tab_stops(self.cols);
+        self.saved_grid = None;
+        self.palette_overrides = vec![None; PALETTE_SLOTS];
+        self.title_stack.clear();
+        self.links.clear();
+        self.link_ids.clear();
+        self.link_compact_at = LINK_COMPACT_MIN;
+        self.active_link = None;
+        self.hovered_link = None;
+        self.last_char = None;
+        self.clear_selection();
     }
 }
 
 impl Perform for Terminal {
     fn print(&mut self, c: char) {
-        if self.cursor_x >= self.cols {
-            self.new_line();
-            self.cursor_x = 0;
+        let set = self.single_shift.take().unwrap_or(self.active_charset);
+        let c = self.charsets[set].translate(c);
+        if c.width() == Some(0) {
+            self.add_mark(c);
+            return;
         }
-        self.grid[self.cursor_y][self.cursor_x] = Cell {
+        // Deferred wrap: only now that another char arrives do we move down
+        if self.pending_wrap {
+            self.pending_wrap = false;
+            if self.autowrap {
+                self.new_line();
+                self.cursor_x = 0;
+            }
+        }
+        let width = if is_wide(c) && self.cols > 1 { 2 } else { 1 };
+        if width == 2 && self.cursor_x + 1 == self.cols {
+            // Both halves go on the next line, or squeeze into the last two columns
+            if self.autowrap {
+                self.new_line();
+                self.cursor_x = 0;
+            } else {
+                self.cursor_x -= 1;
+            }
+        }
+
+        let mut cell = Cell {
             char: c,
+            marks: ['\0'; 2],
             fg: self.current_fg,
             bg: self.current_bg,
-            inverse: self.current_inverse,
+            attrs: self.current_attrs,
+            underline: self.current_underline,
+            underline_color: self.current_underline_color,
+            link: self.active_link,
         };
-        self.cursor_x += 1;
+        let overstrike = self.overstrike_cell.take() == Some((self.cursor_x, self.cursor_y));
+        if overstrike && self.overstrike && !self.insert_mode {
+            let old = self.grid[self.cursor_y][self.cursor_x];
+            if old.char == c && c != ' ' && c != '_' {
+                cell = old;
+                cell.attrs.set(Attrs::BOLD, true);
+            } else if c == '_' && !matches!(old.char, ' ' | '_') {
+                cell = Cell { underline: UnderlineStyle::Single, ..old };
+            } else if old.char == '_' && !matches!(c, ' ' | '_') {
+                cell.underline = UnderlineStyle::Single;
+            }
+        }
+        // Inserting on the right half of a wide char goes in front of all of it
+        if self.insert_mode {
+            self.cursor_x = self.char_start(self.cursor_y, self.cursor_x);
+        }
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        self.last_printed = (width == 1).then_some((x, y));
+        let cells = [cell, Cell { char: '\0', ..cell }];
+        if self.insert_mode {
+            let row = &mut self.grid[y];
+            let len = row.len();
+            row.splice(x..x, cells[..width].iter().copied());
+            row.truncate(len);
+            // A wide character pushed half off the end is cut to a blank
+            if row.last().is_some_and(|cell| is_wide(cell.char)) {
+                row[len - 1].char = ' ';
+            }
+        } else {
+            self.split_wide(y, x);
+            self.split_wide(y, x + width - 1);
+            self.grid[y][x..x + width].copy_from_slice(&cells[..width]);
+        }
+        if self.cursor_x + width < self.cols {
+            self.cursor_x += width;
+        } else {
+            // Stay on the last column until the next printable char
+            self.cursor_x = self.cols - 1;
+            self.pending_wrap = self.autowrap;
+        }
+        self.last_char = Some(c);
     }
 
     fn execute(&mut self, byte: u8) {
+        if matches!(byte, b'\n' | b'\r' | 0x08 | b'\t') {
+            self.pending_wrap = false;
+        }
+        let printed = self.last_printed.take();
+        self.overstrike_cell = None;
         match byte {
-            b'\n' => self.new_line(),
+            b'\n' => {
+                self.new_line();
+                if self.newline_mode { self.cursor_x = 0; }
+            }
+            b'\t' => self.tab_forward(1),
             b'\r' => self.cursor_x = 0,
-            0x08 => { if self.cursor_x > 0 { self.cursor_x -= 1; } }
+            0x08 if self.cursor_x > 0 => {
+                self.cursor_x -= 1;
+                if printed == Some((self.cursor_x, self.cursor_y)) {
+                    self.overstrike_cell = printed;
+                }
+            }
+            0x07 => self.bell_pending = true,
+            // SO / SI
+            0x0e => self.active_charset = 1,
+            0x0f => self.active_charset = 0,
             _ => {}
         }
     }
 
-    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
-        if params.len() >= 2 {
-            let command = params[0];
-            let text_bytes = params[1];
-            if command == b"0" || command == b"2" {
-                if let Ok(title_str) = std::str::from_utf8(text_bytes) {
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        if params.len() < 2 { return; }
+        match params[0] {
+            b"0" | b"2" => {
+                if let Ok(title_str) = std::str::from_utf8(params[1]) {
                     self.title = title_str.to_string();
                 }
             }
+            // OSC 4 ; index ; spec [; index ; spec ...]
+            b"4" => {
+                for pair in params[1..].chunks(2) {
+                    let [index, spec] = pair else { continue };
+                    let slot = std::str::from_utf8(index).ok().and_then(|i| i.parse::<u8>().ok());
+                    if let Some(slot) = slot {
+                        self.osc_color(slot as usize, &format!("4;{}", slot), spec, bell_terminated);
+                    }
+                }
+            }
+            // OSC 133 semantic prompts; only the prompt start (A) is used
+            b"133" if params[1].first() == Some(&b'A') && !self.is_alt_screen() => {
+                let line = self.screen_start() + self.cursor_y;
+                // A cleared screen reuses lines, so drop marks at or below this one
+                let keep = self.prompt_lines.partition_point(|&l| l < line);
+                self.prompt_lines.truncate(keep);
+                self.prompt_lines.push(line);
+            }
+            // OSC 8 ; params ; URI  (the URI itself may contain ';')
+            b"8" if params.len() >= 3 => {
+                let uri = params[2..].iter()
+                    .map(|part| String::from_utf8_lossy(part))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                self.set_active_link(uri);
+            }
+            // OSC 52 ; selection ; base64 data (or '?' to read)
+            b"52" if params.len() >= 3 => {
+                let selection = String::from_utf8_lossy(params[1]).to_string();
+                if params[2] == b"?" {
+                    self.pending_commands.push(HostCommand::QueryClipboard { selection, bell_terminated });
+                } else if let Ok(bytes) = BASE64.decode(params[2]) {
+                    let text = String::from_utf8_lossy(&bytes).to_string();
+                    let primary = selection == "p";
+                    self.pending_commands.push(HostCommand::SetClipboard { primary, text });
+                }
+            }
+            b"10" => self.osc_color(FG_SLOT, "10", params[1], bell_terminated),
+            b"11" => self.osc_color(BG_SLOT, "11", params[1], bell_terminated),
+            _ => {}
         }
     }
 
-    fn csi_dispatch(&mut self, params: &Params, _intermediates: &[u8], _ignore: bool, action: char) {
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        self.last_printed = None;
+        self.overstrike_cell = None;
+        match (intermediates, byte) {
+            ([], b'c') => self.full_reset(),
+            // SCS: designate a character set into G0-G3
+            ([slot @ (b'(' | b')' | b'*' | b'+')], _) => {
+                if let Some(charset) = Charset::from_final(byte) {
+                    self.charsets[(slot - b'(') as usize] = charset;
+                }
+            }
+            // LS2 / LS3, and SS2 / SS3 for just the next character
+            ([], b'n') => self.active_charset = 2,
+            ([], b'o') => self.active_charset = 3,
+            ([], b'N') => self.single_shift = Some(2),
+            ([], b'O') => self.single_shift = Some(3),
+            // DECKPAM / DECKPNM (ESC = / ESC >) fall through and are ignored:
+            // the keypad always sends its plain characters
+            // HTS: set a tab stop at the cursor column
+            ([], b'H') => self.tab_stops[self.cursor_x] = true,
+            // DECALN: fill the screen with 'E' to check alignment
+            ([b'#'], b'8') => {
+                let cell = Cell { char: 'E', ..Cell::default() };
+                for row in &mut self.grid {
+                    row.fill(cell);
+                }
+                self.cursor_x = 0;
+                self.cursor_y = 0;
+                self.pending_wrap = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        self.last_printed = None;
+        self.overstrike_cell = None;
         let p = |i: usize| -> usize {
             let val = params.iter().nth(i).map(|x| x[0]).unwrap_or(1);
             if val == 0 { 1 } else { val as usize }
         };
 
+        // Anything that moves the cursor or edits the line cancels a pending wrap
+        if matches!(action, 'A'..='H' | 'f' | 'd' | 'r' | 'u' | 'J' | 'K' | 'L' | 'M' | 'P' | '@' | 'X' | 'I' | 'Z' | 'a' | 'e') {
+            self.pending_wrap = false;
+        }
+
         match action {
-            'A' => self.cursor_y = self.cursor_y.saturating_sub(p(0)),
-            'B' => self.cursor_y = (self.cursor_y + p(0)).min(self.rows - 1),
-            'C' => self.cursor_x = (self.cursor_x + p(0)).min(self.cols - 1),
+            // CUU/CUD stop at the margins when they start inside the scroll
+            // region, and at the screen edges otherwise
+            'A' => {
+                let top = if self.cursor_y >= self.scroll_top { self.scroll_top } else { 0 };
+                self.cursor_y = self.cursor_y.saturating_sub(p(0)).max(top);
+            }
+            // VPR and HPR are the "position relative" spellings of CUD and CUF
+            'B' | 'e' => {
+                let bottom = if self.cursor_y <= self.scroll_bottom { self.scroll_bottom } else { self.rows - 1 };
+                self.cursor_y = (self.cursor_y + p(0)).min(bottom);
+            }
+            'C' | 'a' => self.cursor_x = (self.cursor_x + p(0)).min(self.cols - 1),
             'D' => self.cursor_x = self.cursor_x.saturating_sub(p(0)),
             'H' | 'f' => {
                 let row = p(0).saturating_sub(1);
                 let col = p(1).saturating_sub(1);
-                self.cursor_y = row.min(self.rows - 1);
+                self.cursor_y = self.absolute_row(row);
                 self.cursor_x = col.min(self.cols - 1);
             }
             'G' => self.cursor_x = (p(0).saturating_sub(1)).min(self.cols - 1),
-            'd' => self.cursor_y = (p(0).saturating_sub(1)).min(self.rows - 1),
+            'd' => self.cursor_y = self.absolute_row(p(0).saturating_sub(1)),
+            'I' => self.tab_forward(p(0)),
+            'Z' => self.tab_backward(p(0)),
+            // TBC: 0 clears the stop at the cursor, 3 clears them all
+            'g' => match params.iter().next().map(|x| x[0]).unwrap_or(0) {
+                0 => self.tab_stops[self.cursor_x] = false,
+                3 => self.tab_stops.fill(false),
+                _ => {}
+            },
             'J' => {
                 let param = params.iter().next().map(|x| x[0]).unwrap_or(0);
-                let clear_cell = |c: &mut Cell| {
-                    c.char = ' ';
-                    c.fg = Color::DefaultFg;
-                    c.bg = Color::DefaultBg;
-                    c.inverse = false;
-                };
+                let blank = self.blank_cell();
+                let clear_cell = |c: &mut Cell| *c = blank;
                 match param {
                     2 => { for row in &mut self.grid { for cell in row { clear_cell(cell); } } self.cursor_x = 0; self.cursor_y = 0; },
-                    0 | _ => {
+                    1 => {
+                        for y in 0..self.cursor_y { for cell in &mut self.grid[y] { clear_cell(cell); } }
+                        for x in 0..=self.cursor_x.min(self.cols - 1) { clear_cell(&mut self.grid[self.cursor_y][x]); }
+                    }
+                    _ => {
                         if self.cursor_y < self.rows { for x in self.cursor_x..self.cols { clear_cell(&mut self.grid[self.cursor_y][x]); } }
                         for y in (self.cursor_y + 1)..self.rows { for cell in &mut self.grid[y] { clear_cell(cell); } }
                     }
@@ -293,16 +1587,12 @@ impl Perform for Terminal {
             }
             'K' => {
                 let param = params.iter().next().map(|x| x[0]).unwrap_or(0);
-                let clear_cell = |c: &mut Cell| {
-                    c.char = ' ';
-                    c.fg = Color::DefaultFg;
-                    c.bg = Color::DefaultBg;
-                    c.inverse = false;
-                };
+                let blank = self.blank_cell();
+                let clear_cell = |c: &mut Cell| *c = blank;
                 match param {
                     2 => { for cell in &mut self.grid[self.cursor_y] { clear_cell(cell); } },
-                    1 => { for x in 0..self.cursor_x { clear_cell(&mut self.grid[self.cursor_y][x]); } },
-                    0 | _ => { for x in self.cursor_x..self.cols { clear_cell(&mut self.grid[self.cursor_y][x]); } }
+                    1 => { for x in 0..=self.cursor_x.min(self.cols - 1) { clear_cell(&mut self.grid[self.cursor_y][x]); } },
+                    _ => { for x in self.cursor_x..self.cols { clear_cell(&mut self.grid[self.cursor_y][x]); } }
                 }
             }
             // FIX: Updated L (Insert Line) to respect margins
@@ -333,92 +1623,196 @@ impl Perform for Terminal {
                     }
                 }
             }
+            // DCH and ICH work on whole wide characters: from the first half
+            // of one under the cursor, and through the end of one the range ends in
             'P' => {
-                let count = p(0);
-                let cx = self.cursor_x;
                 let cy = self.cursor_y;
+                let cx = self.char_start(cy, self.cursor_x);
                 let blank = self.blank_cell();
-                for _ in 0..count {
-                    if cx < self.grid[cy].len() {
-                        self.grid[cy].remove(cx);
-                        self.grid[cy].push(blank);
-                    }
+                let row = &mut self.grid[cy];
+                let len = row.len();
+                if cx < len {
+                    let mut end = (cx + p(0)).min(len);
+                    if row.get(end).is_some_and(|cell| cell.char == '\0') { end += 1; }
+                    row.drain(cx..end);
+                    row.resize(len, blank);
                 }
             }
             '@' => {
+                let cy = self.cursor_y;
+                let cx = self.char_start(cy, self.cursor_x);
+                let blank = self.blank_cell();
+                let row = &mut self.grid[cy];
+                let len = row.len();
+                if cx < len {
+                    row.splice(cx..cx, std::iter::repeat_n(blank, p(0).min(len - cx)));
+                    row.truncate(len);
+                    if row.last().is_some_and(|cell| is_wide(cell.char)) {
+                        row[len - 1].char = ' ';
+                    }
+                }
+            }
+            'p' if intermediates == b"!" => self.soft_reset(),
+            // XTVERSION: name and version as a DCS string
+            'q' if intermediates == b">" => {
+                let reply = format!("\x1bP>|RoseTerm({})\x1b\\", env!("CARGO_PKG_VERSION"));
+                self.respond(&reply);
+            }
+            // Primary DA: a VT220 (62) with ANSI color (22)
+            'c' if intermediates.is_empty() && p(0) <= 1 => self.respond("\x1b[?62;22c"),
+            // DSR: 5 asks for the status (always fine), 6 for the cursor
+            // position, which DECOM makes relative to the scroll region
+            'n' if intermediates.is_empty() => match p(0) {
+                5 => self.respond("\x1b[0n"),
+                6 => {
+                    let row = if self.origin_mode { self.cursor_y.saturating_sub(self.scroll_top) } else { self.cursor_y };
+                    let reply = format!("\x1b[{};{}R", row + 1, self.cursor_x + 1);
+                    self.respond(&reply);
+                }
+                _ => {}
+            },
+            // Secondary DA: a VT220 (1), the version as MMmmpp, no ROM cartridge
+            'c' if intermediates == b">" => {
+                let reply = format!("\x1b[>1;{};0c", version_code());
+                self.respond(&reply);
+            }
+            // DECSCUSR: odd styles blink, even ones are steady, 0 is the user's default
+            'q' if intermediates == b" " => {
+                let style = params.iter().next().map(|x| x[0]).unwrap_or(0);
+                (self.cursor_shape, self.cursor_blink) = match style {
+                    0 => self.default_cursor,
+                    1 | 2 => (CursorShape::Block, style % 2 == 1),
+                    3 | 4 => (CursorShape::Underline, style % 2 == 1),
+                    5 | 6 => (CursorShape::Bar, style % 2 == 1),
+                    _ => return,
+                };
+            }
+            // Window manipulation (XTWINOPS)
+            't' if intermediates.is_empty() => {
+                let param = |i: usize| params.iter().nth(i).map(|x| x[0] as usize).unwrap_or(0);
+                match param(0) {
+                    8 => {
+                        // Zero means "leave this dimension alone"
+                        let rows = if param(1) == 0 { self.rows } else { param(1) };
+                        let cols = if param(2) == 0 { self.cols } else { param(2) };
+                        self.pending_commands.push(HostCommand::ResizeWindow { rows, cols });
+                    }
+                    14 => {
+                        let (width, height) = self.pixel_size;
+                        let reply = format!("\x1b[4;{};{}t", height, width);
+                        self.respond(&reply);
+                    }
+                    18 => {
+                        let reply = format!("\x1b[8;{};{}t", self.rows, self.cols);
+                        self.respond(&reply);
+                    }
+                    // XTPUSHTITLE / XTPOPTITLE. The icon name (;1) shares the
+                    // window title's stack.
+                    22 => {
+                        if self.title_stack.len() >= TITLE_STACK_LIMIT {
+                            self.title_stack.remove(0);
+                        }
+                        self.title_stack.push(self.title.clone());
+                    }
+                    23 => {
+                        if let Some(title) = self.title_stack.pop() {
+                            self.title = title;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            'X' => {
                 let count = p(0);
                 let cx = self.cursor_x;
                 let cy = self.cursor_y;
                 let blank = self.blank_cell();
-                for _ in 0..count {
-                    if cx < self.cols {
-                        self.grid[cy].insert(cx, blank);
-                        self.grid[cy].pop();
+                let end = (cx + count).min(self.cols);
+                if end > cx {
+                    // Erasing half of a wide character erases all of it
+                    self.split_wide(cy, cx);
+                    self.split_wide(cy, end - 1);
+                }
+                for x in cx..end {
+                    self.grid[cy][x] = blank;
+                }
+            }
+            'b' => {
+                if let Some(c) = self.last_char {
+                    let count = p(0).min(self.cols);
+                    for _ in 0..count {
+                        self.print(c);
                     }
                 }
             }
+            // XTSAVE / XTRESTORE: CSI ? Pm s / r
+            's' | 'r' if intermediates == b"?" => {
+                for p in params {
+                    if action == 's' { self.save_private_mode(p[0]); } else { self.restore_private_mode(p[0]); }
+                }
+            }
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
             // FIX: Added 'r' (DECSTBM - Set Top and Bottom Margins)
-            'r' => {
+            'r' if intermediates.is_empty() => {
                 let top = p(0).saturating_sub(1);
-                // If param 1 is missing, it usually defaults to bottom of screen
-                let bot = if params.len() > 1 { p(1).saturating_sub(1) } else { self.rows - 1 };
+                // A missing or zero bottom means the last line, so a bare
+                // CSI r resets the region to the whole screen
+                let bot = params.iter().nth(1)
+                    .map(|x| x[0] as usize)
+                    .filter(|&v| v != 0)
+                    .unwrap_or(self.rows);
 
                 self.scroll_top = top.min(self.rows - 1);
-                self.scroll_bottom = bot.min(self.rows - 1);
+                self.scroll_bottom = bot.saturating_sub(1).min(self.rows - 1);
 
                 // Validation: Bottom must be > Top
                 if self.scroll_bottom <= self.scroll_top {
-                    self.scroll_top = 0;
-                    self.scroll_bottom = self.rows.saturating_sub(1);
+                    self.reset_margins();
                 }
 
-                // CSI r always moves cursor to (0,0) according to spec
-                self.cursor_x = 0;
-                self.cursor_y = 0;
+                // CSI r always moves cursor home according to spec
+                self.home_cursor();
             }
-            'h' => {
-                 for p in params {
-                     match p[0] {
-                         1000 | 1002 | 1006 | 1015 => self.mouse_reporting = true,
-                         25 => { }
-                         _ => {}
-                     }
-                 }
-            }
-            'l' => {
-                 for p in params {
-                     match p[0] {
-                         1000 | 1002 | 1006 | 1015 => self.mouse_reporting = false,
-                         25 => { }
-                         _ => {}
-                     }
-                 }
+            'h' | 'l' => {
+                let enable = action == 'h';
+                let private = intermediates.first() == Some(&b'?');
+                for p in params {
+                    if private {
+                        self.set_private_mode(p[0], enable);
+                    } else {
+                        self.set_ansi_mode(p[0], enable);
+                    }
+                }
             }
             'm' => {
-                if params.len() == 0 {
-                    self.current_fg = Color::DefaultFg;
-                    self.current_bg = Color::DefaultBg;
-                    self.current_inverse = false;
+                if params.is_empty() {
+                    self.reset_sgr();
                     return;
                 }
-                for p_iter in params {
-                    match p_iter[0] {
-                        0 => { self.current_fg = Color::DefaultFg; self.current_bg = Color::DefaultBg; self.current_inverse = false; }
-                        1 => {
-                            self.current_fg = match self.current_fg {
-                                Color::Black => Color::BrightBlack,
-                                Color::Red => Color::BrightRed,
-                                Color::Green => Color::BrightGreen,
-                                Color::Yellow => Color::BrightYellow,
-                                Color::Blue => Color::BrightBlue,
-                                Color::Magenta => Color::BrightMagenta,
-                                Color::Cyan => Color::BrightCyan,
-                                Color::White => Color::BrightWhite,
-                                _ => self.current_fg,
-                            };
-                        }
-                        7 => self.current_inverse = true,
-                        27 => self.current_inverse = false,
+                let mut groups = params.iter();
+                while let Some(group) = groups.next() {
+                    let code = group[0];
+                    if let Some(&(_, attrs, on)) = SGR_ATTRS.iter().find(|(c, _, _)| *c == code) {
+                        self.current_attrs.set(attrs, on);
+                        continue;
+                    }
+                    match code {
+                        0 => self.reset_sgr(),
+                        38 => if let Some(color) = extended_color(group, &mut groups) { self.current_fg = color },
+                        48 => if let Some(color) = extended_color(group, &mut groups) { self.current_bg = color },
+                        58 => if let Some(color) = extended_color(group, &mut groups) { self.current_underline_color = Some(color) },
+                        59 => self.current_underline_color = None,
+                        4 => self.current_underline = match group.get(1).copied().unwrap_or(1) {
+                            0 => UnderlineStyle::None,
+                            2 => UnderlineStyle::Double,
+                            3 => UnderlineStyle::Curly,
+                            4 => UnderlineStyle::Dotted,
+                            5 => UnderlineStyle::Dashed,
+                            _ => UnderlineStyle::Single,
+                        },
+                        21 => self.current_underline = UnderlineStyle::Double,
+                        24 => self.current_underline = UnderlineStyle::None,
                         30 => self.current_fg = Color::Black,
                         31 => self.current_fg = Color::Red,
                         32 => self.current_fg = Color::Green,
@@ -452,4 +1846,564 @@ impl Perform for Terminal {
             _ => {}
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_string(term: &Terminal, row: usize) -> String {
+        row_text(&term.grid[row])
+    }
+
+    #[test]
+    fn cup_moves_cursor() {
+        let mut term = Terminal::new(80, 24);
+        term.feed(b"\x1b[5;10H");
+        assert_eq!((term.cursor_x, term.cursor_y), (9, 4));
+        term.feed(b"\x1b[H");
+        assert_eq!((term.cursor_x, term.cursor_y), (0, 0));
+        term.feed(b"\x1b[99;999H");
+        assert_eq!((term.cursor_x, term.cursor_y), (79, 23));
+    }
+
+    #[test]
+    fn erase_in_line_and_display() {
+        let mut term = Terminal::new(20, 5);
+        term.feed(b"hello world\r\nsecond");
+        term.feed(b"\x1b[1;6H\x1b[K");
+        assert_eq!(row_string(&term, 0), "hello");
+        term.feed(b"\x1b[1;3H\x1b[1K");
+        assert_eq!(row_string(&term, 0), "   lo");
+        term.feed(b"\x1b[2;2H\x1b[1J");
+        assert_eq!(row_string(&term, 0), "");
+        assert_eq!(row_string(&term, 1), "  cond");
+        term.feed(b"\x1b[2J");
+        assert_eq!(row_string(&term, 0), "");
+        assert_eq!(row_string(&term, 1), "");
+    }
+
+    #[test]
+    fn erase_fills_with_the_current_background() {
+        let mut term = Terminal::new(10, 3);
+        term.feed(b"abcdef\x1b[44;1;7m\x1b[1;4H\x1b[K\x1b[3;1H\x1b[2K");
+        let row = term.get_visible_row(0);
+        assert_eq!((row[2].char, row[2].bg), ('c', Color::DefaultBg));
+        for cell in &row[3..] {
+            assert_eq!((cell.char, cell.bg, cell.attrs), (' ', Color::Blue, Attrs::default()));
+        }
+        assert!(term.get_visible_row(2).iter().all(|cell| cell.bg == Color::Blue));
+        // Untouched rows keep the default
+        assert!(term.get_visible_row(1).iter().all(|cell| cell.bg == Color::DefaultBg));
+
+        term.feed(b"\x1b[42m\x1b[2J");
+        assert!(term.grid.iter().flatten().all(|cell| cell.bg == Color::Green && cell.char == ' '));
+    }
+
+    #[test]
+    fn line_feed_scrolls_only_the_region() {
+        let mut term = Terminal::new(10, 5);
+        term.feed(b"a\r\nb\r\nc\r\nd\r\ne");
+        term.feed(b"\x1b[2;4r\x1b[4;1H\n");
+        let rows: Vec<String> = (0..5).map(|r| row_string(&term, r)).collect();
+        assert_eq!(rows, ["a", "c", "d", "", "e"]);
+        // Scrolling inside a region that doesn't start at the top keeps history empty
+        assert!(term.history.is_empty());
+    }
+
+    #[test]
+    fn sgr_sets_and_resets_colors() {
+        let mut term = Terminal::new(10, 2);
+        term.feed(b"\x1b[31;42mA\x1b[91mB\x1b[0mC\x1b[7mD");
+        let row = &term.grid[0];
+        assert_eq!((row[0].fg, row[0].bg), (Color::Red, Color::Green));
+        assert_eq!(row[1].fg, Color::BrightRed);
+        assert_eq!((row[2].fg, row[2].bg), (Color::DefaultFg, Color::DefaultBg));
+        assert!(row[3].attrs.contains(Attrs::INVERSE));
+    }
+
+    #[test]
+    fn scrolling_past_the_top_of_history_stops_at_the_oldest_line() {
+        let mut term = Terminal::new(10, 4);
+        term.feed(b"1\r\n2\r\n3\r\n4\r\n5\r\n6");
+        assert_eq!(term.history.len(), 2);
+
+        term.scroll_up(100);
+        assert_eq!(term.scroll_offset, 2);
+        assert_eq!(row_text(term.get_visible_row(0)), "1");
+
+        // An offset larger than the history must not panic
+        term.resize(10, 2);
+        term.scroll_offset = 50;
+        for row in 0..term.rows {
+            term.get_visible_row(row);
+        }
+        assert_eq!(row_text(term.get_visible_row(0)), "1");
+    }
+
+    #[test]
+    fn absolute_lines_survive_history_trimming() {
+        let mut term = Terminal::new(10, 2);
+        term.set_scrollback_lines(Some(3));
+        term.feed(b"0\r\n1\r\n2\r\n3");
+        assert_eq!((term.history_start, term.total_lines()), (0, 4));
+        term.start_selection(0, 0, false);
+        term.update_selection(0, 1);
+        assert_eq!(term.get_selected_text(), "2\n3");
+        term.search_matches = term.search("1");
+        assert_eq!(term.search_matches[0].0, 1);
+
+        term.feed(b"\r\n4\r\n5\r\n6");
+        // Two lines dropped; the rest keep their numbers
+        assert_eq!((term.history_start, term.total_lines()), (2, 5));
+        assert!(term.line_at_absolute(1).is_none());
+        assert_eq!(row_text(term.line_at_absolute(2).unwrap()), "2");
+        assert_eq!(row_text(term.line_at_absolute(6).unwrap()), "6");
+        assert!(term.line_at_absolute(7).is_none());
+        assert_eq!(term.visible_line(0), 5);
+        assert_eq!(term.get_selected_text(), "2\n3");
+        assert!(term.search_matches.is_empty());
+
+        term.scroll_up(10);
+        assert_eq!((term.scroll_offset, term.visible_line(0)), (3, 2));
+        assert_eq!(row_text(term.get_visible_row(1)), "3");
+
+        // Trimming the selection's first line clears it
+        term.feed(b"\r\n7");
+        assert_eq!(term.selection_start, None);
+    }
+
+    #[test]
+    fn backspace_overstrike_makes_bold_and_underline() {
+        let mut term = Terminal::new(10, 1);
+        term.overstrike = true;
+        term.feed(b"N\x08Na\x08__\x08b_\x08_x\x08y");
+        let row = term.get_visible_row(0);
+        assert_eq!(row_text(row), "Nab_y");
+        assert!(row[0].attrs.contains(Attrs::BOLD));
+        assert_eq!(row[1].underline, UnderlineStyle::Single);
+        assert_eq!(row[2].underline, UnderlineStyle::Single);
+        // Underscore over underscore stays a plain underscore
+        assert_eq!((row[3].underline, row[3].attrs), (UnderlineStyle::None, Attrs::default()));
+        // Different characters just overwrite
+        assert_eq!((row[4].underline, row[4].attrs), (UnderlineStyle::None, Attrs::default()));
+
+        term.overstrike = false;
+        term.feed(b"\rN\x08N");
+        assert!(!term.get_visible_row(0)[0].attrs.contains(Attrs::BOLD));
+
+        // Readline deleting the middle 'b' of "abb" redraws the last one over it
+        let mut term = Terminal::new(10, 1);
+        term.overstrike = true;
+        term.feed(b"abb\x08\x08b ");
+        assert_eq!(row_text(term.get_visible_row(0)), "ab");
+        assert!(term.get_visible_row(0).iter().all(|cell| !cell.attrs.contains(Attrs::BOLD)));
+        // A cursor move in between isn't overstrike either
+        term.feed(b"\rx\x1b[C\x1b[D\x08x");
+        assert!(!term.get_visible_row(0)[0].attrs.contains(Attrs::BOLD));
+    }
+
+    #[test]
+    fn wide_characters_are_edited_whole() {
+        let chars = |term: &Terminal| term.grid[0].iter().map(|cell| cell.char).collect::<String>();
+        let mut term = Terminal::new(8, 1);
+        term.feed("a\u{4e2d}b".as_bytes());
+        assert_eq!(chars(&term), "a\u{4e2d}\0b    ");
+        assert_eq!(term.cursor_x, 4);
+
+        // DCH on the first half removes both
+        term.feed(b"\x1b[2G\x1b[P");
+        assert_eq!(chars(&term), "ab      ");
+
+        // DCH on the second half, and ICH, also take the whole character
+        term.feed("\r\u{4e2d}\u{6587}\x1b[2G\x1b[P".as_bytes());
+        assert_eq!(chars(&term), "\u{6587}\0      ");
+        term.feed(b"\x1b[2G\x1b[@");
+        assert_eq!(chars(&term), " \u{6587}\0     ");
+
+        // Erasing or overwriting half of one blanks the other half
+        term.feed(b"\x1b[3G\x1b[X");
+        assert_eq!(chars(&term), "        ");
+        term.feed("\x1b[7G\u{4e2d}".as_bytes());
+        assert_eq!(row_text(&term.grid[0]), "      \u{4e2d}");
+        term.feed(b"\x1b[8Gx");
+        assert_eq!(chars(&term), "       x");
+    }
+
+    #[test]
+    fn decscusr_zero_restores_the_configured_cursor() {
+        let mut term = Terminal::new(10, 2);
+        term.set_default_cursor(CursorShape::Bar, false);
+        term.feed(b"\x1b[3 q");
+        assert_eq!((term.cursor_shape, term.cursor_blink), (CursorShape::Underline, true));
+        term.feed(b"\x1b[2 q");
+        assert_eq!((term.cursor_shape, term.cursor_blink), (CursorShape::Block, false));
+        term.feed(b"\x1b[0 q");
+        assert_eq!((term.cursor_shape, term.cursor_blink), (CursorShape::Bar, false));
+        term.feed(b"\x1b[5 q\x1bc");
+        assert_eq!((term.cursor_shape, term.cursor_blink), (CursorShape::Bar, false));
+    }
+
+    #[test]
+    fn combining_marks_join_the_previous_character() {
+        let mut term = Terminal::new(4, 2);
+        term.feed("e\u{301}x\u{4e2d}\u{308}".as_bytes());
+        let row = &term.grid[0];
+        assert_eq!((row[0].char, row[0].marks), ('e', ['\u{301}', '\0']));
+        assert_eq!(row[1].marks, ['\0'; 2]);
+        // On a wide character, and at the right margin before wrapping
+        assert_eq!(row[2].marks, ['\u{308}', '\0']);
+        assert_eq!(term.cursor_x, 3);
+        assert_eq!(row_text(row), "e\u{301}x\u{4e2d}\u{308}");
+
+        // A third mark is dropped, and one at the start of a line has nothing to join
+        term.feed("\r\n\u{301}a\u{301}\u{302}\u{303}".as_bytes());
+        assert_eq!(row_text(&term.grid[1]), "a\u{301}\u{302}");
+    }
+
+    #[test]
+    fn plain_text_snapshots() {
+        let mut term = Terminal::new(6, 3);
+        term.feed("one\r\n\x1b[1mtwo\x1b[0m  \r\n\u{4e2d}\u{6587}!\r\nfour".as_bytes());
+        assert_eq!(term.screen_to_string(), "two\n\u{4e2d}\u{6587}!\nfour\n");
+        assert_eq!(term.scrollback_to_string(), "one\ntwo\n\u{4e2d}\u{6587}!\nfour\n");
+
+        // The screen snapshot follows the view, and keeps empty rows
+        term.scroll_up(1);
+        assert_eq!(term.screen_to_string(), "one\ntwo\n\u{4e2d}\u{6587}!\n");
+        term.feed(b"\x1b[2J");
+        term.scroll_down(1);
+        assert_eq!(term.screen_to_string(), "\n\n\n");
+    }
+
+    #[test]
+    fn charset_designation_and_shifts() {
+        let mut term = Terminal::new(12, 2);
+        // SS3 translates exactly one character through G3
+        term.feed(b"\x1b+0\x1bOqq");
+        assert_eq!(row_text(&term.grid[0]), "\u{2500}q");
+
+        // SO/SI lock G1 in and out; G0 stays ASCII meanwhile
+        term.feed(b"\x1b)0\x0elqk\x0fx\x1b(A#\x1b(B#");
+        assert_eq!(row_text(&term.grid[0]), "\u{2500}q\u{250c}\u{2500}\u{2510}x\u{a3}#");
+
+        // SS2 shifts past a locked set, then it applies again
+        term.feed(b"\r\n\x1b*A\x0e\x1bN#x\x0f");
+        assert_eq!(row_text(&term.grid[1]), "\u{a3}\u{2502}");
+
+        term.feed(b"\x1bc");
+        assert_eq!((term.charsets, term.active_charset), ([Charset::Ascii; 4], 0));
+    }
+
+    #[test]
+    fn scrolling_reports_whether_the_view_moved() {
+        let mut term = Terminal::new(10, 2);
+        term.feed(b"1\r\n2\r\n3\r\n4");
+        assert!(!term.scroll_down(1));
+        assert!(term.scroll_up(5));
+        assert_eq!(term.scroll_offset, 2);
+        assert!(!term.scroll_up(1));
+
+        // A stale offset past a shrunk history is clamped, not kept
+        term.scroll_offset = 40;
+        assert!(term.scroll_down(1));
+        assert_eq!(term.scroll_offset, 1);
+        assert!(term.scroll_to_bottom());
+        assert!(!term.scroll_to_bottom());
+    }
+
+    #[test]
+    fn synchronized_update_mode() {
+        let mut term = Terminal::new(10, 5);
+        term.feed(b"\x1b[?2026h");
+        assert!(term.sync_update);
+        term.feed(b"\x1b[?2026l");
+        assert!(!term.sync_update);
+        term.feed(b"\x1b[?2026h\x1bc");
+        assert!(!term.sync_update);
+    }
+
+    #[test]
+    fn hyperlinks_are_interned_and_dropped_with_history() {
+        let link = |uri: &str| format!("\x1b]8;;{}\x1b\\x\x1b]8;;\x1b\\\r\n", uri);
+        let mut term = Terminal::new(10, 2);
+        term.set_scrollback_lines(Some(2));
+        term.feed(format!("{}{}", link("http://a"), link("http://a")).as_bytes());
+        assert_eq!(term.links, ["http://a"]);
+
+        for n in 0..1000 {
+            term.feed(link(&format!("http://{}", n)).as_bytes());
+        }
+        assert!(term.links.len() < 2 * LINK_COMPACT_MIN);
+        // Lines still on screen or in history keep their targets
+        let uri = |cell: &Cell| term.links[cell.link.unwrap() as usize].as_str();
+        assert_eq!((uri(&term.history[1][0]), uri(&term.grid[0][0])), ("http://998", "http://999"));
+    }
+
+    #[test]
+    fn soft_reset_restores_modes_but_keeps_the_screen() {
+        let mut term = Terminal::new(10, 3);
+        term.feed(b"hi\x1b[?1h\x1b=\x1b[?7l\x1b[4h\x1b[?25l\x1b[1m\x1b[2;3r");
+        term.feed(b"\x1b[!p");
+        assert!(!term.app_cursor_keys && term.autowrap);
+        assert!(!term.insert_mode && term.cursor_visible);
+        assert_eq!(term.current_attrs, Attrs::default());
+        assert_eq!(row_text(term.get_visible_row(0)), "hi");
+    }
+
+    #[test]
+    fn palette_starts_as_xterms() {
+        let mut term = Terminal::new(10, 2);
+        assert_eq!(term.palette_color(1), (205, 0, 0));
+        assert_eq!(term.palette_color(196), (255, 0, 0));
+        term.feed(b"\x1b]4;1;?\x07");
+        assert_eq!(term.pending_output, b"\x1b]4;1;rgb:cdcd/0000/0000\x07");
+    }
+
+    #[test]
+    fn insert_mode_over_a_wide_character() {
+        let mut term = Terminal::new(8, 1);
+        term.feed("a\u{4e2d}b".as_bytes());
+        // Cursor on the right half of the wide char, then insert 'x'
+        term.feed(b"\x1b[3G\x1b[4hx");
+        assert_eq!(row_text(term.get_visible_row(0)), "ax\u{4e2d}b");
+        assert_eq!(term.cursor_x, 2);
+        term.feed(b"y");
+        assert_eq!(row_text(term.get_visible_row(0)), "axy\u{4e2d}b");
+    }
+
+    #[test]
+    fn search_matches_wide_characters() {
+        let mut term = Terminal::new(10, 1);
+        term.feed("a\u{4e2d}\u{6587}b".as_bytes());
+        assert_eq!(term.search("\u{4e2d}\u{6587}"), [(0, 1..5)]);
+        assert_eq!(term.search("\u{6587}B"), [(0, 3..6)]);
+    }
+
+    #[test]
+    fn ech_blanks_cells_without_moving_the_cursor() {
+        let mut term = Terminal::new(8, 1);
+        term.feed(b"abcdefgh\x1b[3G\x1b[3X");
+        assert_eq!(row_text(term.get_visible_row(0)), "ab   fgh");
+        assert_eq!(term.cursor_x, 2);
+        // Past the end of the row it stops at the last column
+        term.feed(b"\x1b[99X");
+        assert_eq!(row_text(term.get_visible_row(0)), "ab");
+        assert_eq!(term.cursor_x, 2);
+    }
+
+    #[test]
+    fn rep_is_clamped_to_the_row_width() {
+        let mut term = Terminal::new(5, 3);
+        term.feed(b"a\x1b[1000b");
+        assert_eq!(term.screen_to_string(), "aaaaa\na\n\n");
+    }
+
+    #[test]
+    fn color_specs_for_osc_and_config() {
+        assert_eq!(parse_color_spec("#ff8000"), Some((255, 128, 0)));
+        assert_eq!(parse_color_spec(" ff8000 "), Some((255, 128, 0)));
+        assert_eq!(parse_color_spec("rgb:ffff/80/0"), Some((255, 128, 0)));
+        assert_eq!(parse_color_spec("rgb:ff/80"), None);
+        assert_eq!(parse_color_spec("#ff80"), None);
+    }
+
+    #[test]
+    fn sgr_extended_colors_in_both_syntaxes() {
+        let mut term = Terminal::new(10, 2);
+        term.feed(b"\x1b[38;5;196;48;2;1;2;3mA");
+        term.feed(b"\x1b[38:2:10:20:30;48:5:17mB");
+        term.feed(b"\x1b[38:2::40:50:60;1mC");
+        let row = &term.grid[0];
+        assert_eq!((row[0].fg, row[0].bg), (Color::Indexed(196), Color::Rgb(1, 2, 3)));
+        assert_eq!((row[1].fg, row[1].bg), (Color::Rgb(10, 20, 30), Color::Indexed(17)));
+        assert_eq!(row[2].fg, Color::Rgb(40, 50, 60));
+        assert!(row[2].attrs.contains(Attrs::BOLD));
+    }
+
+    #[test]
+    fn sgr_underline_styles_and_color() {
+        let mut term = Terminal::new(10, 2);
+        term.feed(b"\x1b[4mA\x1b[4:3;58;5;1mB\x1b[59;21mC\x1b[24mD");
+        let row = &term.grid[0];
+        assert_eq!(row[0].underline, UnderlineStyle::Single);
+        assert_eq!((row[1].underline, row[1].underline_color), (UnderlineStyle::Curly, Some(Color::Indexed(1))));
+        assert_eq!((row[2].underline, row[2].underline_color), (UnderlineStyle::Double, None));
+        assert_eq!(row[3].underline, UnderlineStyle::None);
+    }
+
+    #[test]
+    fn title_stack_push_and_pop() {
+        let mut term = Terminal::new(10, 2);
+        term.feed(b"\x1b]2;shell\x07\x1b[22;2t\x1b]2;vim\x07");
+        assert_eq!(term.title, "vim");
+        term.feed(b"\x1b[23;2t");
+        assert_eq!(term.title, "shell");
+        // Popping an empty stack leaves the title alone
+        term.feed(b"\x1b[23;2t");
+        assert_eq!(term.title, "shell");
+        // XTSMTITLE and DECSWBV share the final byte but aren't window ops
+        term.feed(b"\x1b[22;2t\x1b]2;vim\x07\x1b[>23;2t\x1b[18 t");
+        assert_eq!(term.title, "vim");
+        assert!(term.pending_output.is_empty());
+    }
+
+    #[test]
+    fn cursor_up_and_down_stop_at_the_margins() {
+        let mut term = Terminal::new(10, 10);
+        term.feed(b"\x1b[3;6r\x1b[4;1H\x1b[9A");
+        assert_eq!(term.cursor_y, 2);
+        term.feed(b"\x1b[9B");
+        assert_eq!(term.cursor_y, 5);
+        // Outside the region only the screen edges apply
+        term.feed(b"\x1b[8;1H\x1b[9B");
+        assert_eq!(term.cursor_y, 9);
+        term.feed(b"\x1b[2;1H\x1b[9A");
+        assert_eq!(term.cursor_y, 0);
+    }
+
+    #[test]
+    fn ansi_copy_coalesces_runs() {
+        let mut term = Terminal::new(20, 2);
+        term.feed(b"\x1b[1;31mab\x1b[0m c\x1b[48;5;20m  \x1b[0m");
+        term.start_selection(0, 0, false);
+        term.update_selection(19, 0);
+        assert_eq!(term.get_selected_ansi_text(), "\x1b[0;1;31mab\x1b[0m c\x1b[0;48;5;20m  \x1b[0m");
+        assert_eq!(term.get_selected_text(), "ab c");
+    }
+
+    #[test]
+    fn utf8_split_across_feeds() {
+        let mut term = Terminal::new(10, 2);
+        // U+4E2D is E4 B8 AD; the parser keeps the partial sequence between reads
+        term.feed(b"\xe4\xb8");
+        term.feed(b"\xadx");
+        assert_eq!(term.grid[0][0].char, '\u{4e2d}');
+        assert!(term.grid[0].iter().all(|cell| cell.char != '\u{fffd}'));
+        assert_eq!(row_text(&term.grid[0]).replace(' ', ""), "\u{4e2d}x");
+    }
+
+    #[test]
+    fn osc52_split_across_feeds() {
+        let mut term = Terminal::new(10, 2);
+        // base64 of the UTF-8 for U+4E2D U+6587, cut inside the payload
+        term.feed(b"\x1b]52;c;5Lit");
+        term.feed(b"5paH\x07");
+        assert_eq!(term.pending_commands, vec![HostCommand::SetClipboard {
+            primary: false,
+            text: "\u{4e2d}\u{6587}".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn device_status_reports_are_queued() {
+        let mut term = Terminal::new(10, 5);
+        term.feed(b"\x1b[5n");
+        assert_eq!(term.pending_output, b"\x1b[0n");
+        term.pending_output.clear();
+
+        term.feed(b"\x1b[3;7H\x1b[6n");
+        assert_eq!(term.pending_output, b"\x1b[3;7R");
+        term.pending_output.clear();
+
+        // With origin mode the row counts from the top margin
+        term.feed(b"\x1b[2;4r\x1b[?6h\x1b[2;1H\x1b[6n\x1b[c");
+        assert_eq!(term.pending_output, b"\x1b[2;1R\x1b[?62;22c");
+    }
+
+    #[test]
+    fn identifies_itself_to_xtversion_and_secondary_da() {
+        let mut term = Terminal::new(10, 2);
+        term.feed(b"\x1b[>q");
+        let expected = format!("\x1bP>|RoseTerm({})\x1b\\", env!("CARGO_PKG_VERSION"));
+        assert_eq!(term.pending_output, expected.as_bytes());
+        term.pending_output.clear();
+        term.feed(b"\x1b[>c");
+        assert_eq!(term.pending_output, format!("\x1b[>1;{};0c", version_code()).as_bytes());
+    }
+
+    #[test]
+    fn prompt_marks_follow_history_and_jump() {
+        let mut term = Terminal::new(10, 3);
+        term.set_scrollback_lines(Some(4));
+        for n in 0..4 {
+            term.feed(format!("\x1b]133;A\x07${}\r\nout\r\n", n).as_bytes());
+        }
+        // 8 lines written, 2 on screen above the cursor row, 4 kept in history
+        // and 2 trimmed away along with the first mark
+        assert_eq!(term.prompt_lines, vec![2, 4, 6]);
+        assert_eq!(row_text(term.line_at_absolute(6).unwrap()), "$3");
+
+        // The newest prompt is already the top line
+        term.jump_to_prompt(true);
+        assert_eq!(term.visible_line(0), 4);
+        term.jump_to_prompt(true);
+        assert_eq!(term.visible_line(0), 2);
+        term.jump_to_prompt(true);
+        assert_eq!(term.visible_line(0), 2);
+        term.jump_to_prompt(false);
+        assert_eq!(term.visible_line(0), 4);
+        term.jump_to_prompt(false);
+        assert_eq!(term.scroll_offset, 0);
+    }
+
+    #[test]
+    fn select_word_stops_at_separators() {
+        let mut term = Terminal::new(30, 1);
+        term.feed(b"cat ~/src/main.rs (x)");
+        term.select_word(8, 0, "()");
+        assert_eq!(term.get_selected_text(), "~/src/main.rs");
+        term.select_word(8, 0, "/.()");
+        assert_eq!(term.get_selected_text(), "src");
+        // A separator selects only itself
+        term.select_word(18, 0, "()");
+        assert_eq!(term.get_selected_text(), "(");
+    }
+
+    #[test]
+    fn xtsave_and_xtrestore_private_modes() {
+        let mut term = Terminal::new(10, 5);
+        term.feed(b"\x1b[?1h\x1b[?25l\x1b[?1;25;7s");
+        term.feed(b"\x1b[?1l\x1b[?25h\x1b[?7l");
+        assert!(!term.app_cursor_keys && term.cursor_visible && !term.autowrap);
+        // Only the modes named are restored
+        term.feed(b"\x1b[?1;25r");
+        assert!(term.app_cursor_keys && !term.cursor_visible && !term.autowrap);
+        term.feed(b"\x1b[?7r");
+        assert!(term.autowrap);
+
+        // 1048 saves and restores just the cursor
+        term.feed(b"\x1b[3;4H\x1b[?1048h\x1b[H\x1b[?1048l");
+        assert_eq!((term.cursor_x, term.cursor_y), (3, 2));
+        assert!(!term.is_alt_screen());
+    }
+
+    #[test]
+    fn mouse_modes_replace_each_other() {
+        let mut term = Terminal::new(10, 5);
+        term.feed(b"\x1b[?1000h\x1b[?1003h\x1b[?1006h");
+        assert_eq!((term.mouse_mode, term.mouse_encoding), (MouseMode::AnyEvent, MouseEncoding::Sgr));
+        // Resetting a mode that isn't active leaves the active one alone
+        term.feed(b"\x1b[?1000l");
+        assert_eq!(term.mouse_mode, MouseMode::AnyEvent);
+        term.feed(b"\x1b[?1003l\x1b[?1006l");
+        assert_eq!((term.mouse_mode, term.mouse_encoding), (MouseMode::Off, MouseEncoding::Normal));
+    }
+
+    #[test]
+    fn alt_screen_round_trip_resets_scroll_region() {
+        let mut term = Terminal::new(80, 24);
+        term.feed(b"\x1b[5;10r");
+        assert_eq!((term.scroll_top, term.scroll_bottom), (4, 9));
+
+        term.feed(b"\x1b[?1049h\x1b[2;20r\x1b[?1049l");
+        assert_eq!((term.scroll_top, term.scroll_bottom), (0, 23));
+    }
+
+    #[test]
+    fn bare_decstbm_resets_scroll_region() {
+        let mut term = Terminal::new(80, 24);
+        term.feed(b"\x1b[5;10r\x1b[r");
+        assert_eq!((term.scroll_top, term.scroll_bottom), (0, 23));
+    }
+}